@@ -0,0 +1 @@
+ALTER TABLE clients DROP COLUMN require_pkce;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE clients ADD COLUMN require_pkce BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE access_tokens DROP COLUMN family_id;
+
+DROP TABLE refresh_tokens;
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE refresh_tokens (
+    id INTEGER PRIMARY KEY,
+    uid VARCHAR(64) NOT NULL UNIQUE,
+    family_id BIGINT NOT NULL,
+
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    client_id BIGINT NOT NULL REFERENCES clients(id),
+    body TEXT NOT NULL,
+    rotated BOOLEAN NOT NULL DEFAULT FALSE,
+
+    expires INTEGER NOT NULL
+);
+
+ALTER TABLE access_tokens ADD COLUMN family_id BIGINT;
\ No newline at end of file
@@ -0,0 +1,21 @@
+CREATE TABLE access_tokens_old (
+    id INTEGER PRIMARY KEY,
+    uid VARCHAR(64) NOT NULL UNIQUE,
+
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    client_id BIGINT NOT NULL REFERENCES clients(id),
+    body TEXT NOT NULL,
+
+    expires INTEGER NOT NULL,
+    family_id BIGINT
+);
+
+INSERT INTO access_tokens_old
+SELECT id, uid, user_id, client_id, body, expires, family_id FROM access_tokens
+WHERE user_id IS NOT NULL;
+
+DROP TABLE access_tokens;
+ALTER TABLE access_tokens_old RENAME TO access_tokens;
+
+ALTER TABLE clients DROP COLUMN scope;
+ALTER TABLE clients DROP COLUMN grant_types;
\ No newline at end of file
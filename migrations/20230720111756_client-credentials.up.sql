@@ -0,0 +1,20 @@
+ALTER TABLE clients ADD COLUMN grant_types TEXT NOT NULL DEFAULT '["authorization_code","refresh_token"]';
+ALTER TABLE clients ADD COLUMN scope TEXT;
+
+CREATE TABLE access_tokens_new (
+    id INTEGER PRIMARY KEY,
+    uid VARCHAR(64) NOT NULL UNIQUE,
+
+    user_id BIGINT REFERENCES users(id),
+    client_id BIGINT NOT NULL REFERENCES clients(id),
+    body TEXT NOT NULL,
+
+    expires INTEGER NOT NULL,
+    family_id BIGINT
+);
+
+INSERT INTO access_tokens_new
+SELECT id, uid, user_id, client_id, body, expires, family_id FROM access_tokens;
+
+DROP TABLE access_tokens;
+ALTER TABLE access_tokens_new RENAME TO access_tokens;
\ No newline at end of file
@@ -0,0 +1,3 @@
+DROP TABLE revoked_jtis;
+
+ALTER TABLE clients DROP COLUMN access_token_format;
\ No newline at end of file
@@ -0,0 +1,7 @@
+ALTER TABLE clients ADD COLUMN access_token_format VARCHAR(16) NOT NULL DEFAULT 'opaque';
+
+CREATE TABLE revoked_jtis (
+    jti VARCHAR(64) NOT NULL PRIMARY KEY,
+
+    expires INTEGER NOT NULL
+);
\ No newline at end of file
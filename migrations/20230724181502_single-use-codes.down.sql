@@ -0,0 +1,3 @@
+DROP TABLE access_token_jtis;
+
+ALTER TABLE authorization_codes DROP COLUMN family_id;
\ No newline at end of file
@@ -0,0 +1,8 @@
+ALTER TABLE authorization_codes ADD COLUMN family_id BIGINT;
+
+CREATE TABLE access_token_jtis (
+    jti VARCHAR(64) NOT NULL PRIMARY KEY,
+    family_id BIGINT NOT NULL,
+
+    expires INTEGER NOT NULL
+);
\ No newline at end of file
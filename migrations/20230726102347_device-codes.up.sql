@@ -0,0 +1,14 @@
+CREATE TABLE device_codes (
+    id INTEGER PRIMARY KEY,
+    uid VARCHAR(64) NOT NULL UNIQUE,
+    user_code VARCHAR(16) NOT NULL UNIQUE,
+
+    client_id BIGINT NOT NULL REFERENCES clients(id),
+    user_id BIGINT REFERENCES users(id),
+    body TEXT NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    auth_time INTEGER,
+    last_polled INTEGER,
+
+    expires INTEGER NOT NULL
+);
\ No newline at end of file
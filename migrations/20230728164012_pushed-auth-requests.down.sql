@@ -0,0 +1,3 @@
+DROP TABLE pushed_auth_requests;
+
+ALTER TABLE clients DROP COLUMN require_par;
\ No newline at end of file
@@ -0,0 +1,11 @@
+ALTER TABLE clients ADD COLUMN require_par BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE pushed_auth_requests (
+    id INTEGER PRIMARY KEY,
+    uid VARCHAR(64) NOT NULL UNIQUE,
+
+    client_id BIGINT NOT NULL REFERENCES clients(id),
+    params TEXT NOT NULL,
+
+    expires INTEGER NOT NULL
+);
\ No newline at end of file
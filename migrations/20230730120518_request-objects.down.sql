@@ -0,0 +1,3 @@
+ALTER TABLE clients DROP COLUMN request_uris;
+ALTER TABLE clients DROP COLUMN jwks_uri;
+ALTER TABLE clients DROP COLUMN jwks;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE clients ADD COLUMN jwks TEXT;
+ALTER TABLE clients ADD COLUMN jwks_uri TEXT;
+ALTER TABLE clients ADD COLUMN request_uris TEXT NOT NULL DEFAULT '[]';
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE clients ADD COLUMN response_types TEXT NOT NULL DEFAULT '["code"]';
\ No newline at end of file
@@ -0,0 +1,15 @@
+CREATE TABLE access_token_jtis_old (
+    jti VARCHAR(64) NOT NULL PRIMARY KEY,
+    family_id BIGINT NOT NULL,
+
+    expires INTEGER NOT NULL
+);
+
+INSERT INTO access_token_jtis_old
+SELECT jti, family_id, expires FROM access_token_jtis
+WHERE family_id IS NOT NULL;
+
+DROP TABLE access_token_jtis;
+ALTER TABLE access_token_jtis_old RENAME TO access_token_jtis;
+
+DROP TABLE consent_grants;
\ No newline at end of file
@@ -0,0 +1,26 @@
+CREATE TABLE consent_grants (
+    id INTEGER PRIMARY KEY,
+
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    client_id BIGINT NOT NULL REFERENCES clients(id),
+    scope TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    UNIQUE (user_id, client_id)
+);
+
+CREATE TABLE access_token_jtis_new (
+    jti VARCHAR(64) NOT NULL PRIMARY KEY,
+    family_id BIGINT,
+    user_id BIGINT REFERENCES users(id),
+    client_id BIGINT REFERENCES clients(id),
+
+    expires INTEGER NOT NULL
+);
+
+INSERT INTO access_token_jtis_new
+(jti, family_id, expires)
+SELECT jti, family_id, expires FROM access_token_jtis;
+
+DROP TABLE access_token_jtis;
+ALTER TABLE access_token_jtis_new RENAME TO access_token_jtis;
\ No newline at end of file
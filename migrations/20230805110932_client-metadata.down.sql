@@ -0,0 +1,6 @@
+ALTER TABLE clients DROP COLUMN require_auth_time;
+ALTER TABLE clients DROP COLUMN default_max_age;
+ALTER TABLE clients DROP COLUMN tos_uri;
+ALTER TABLE clients DROP COLUMN policy_uri;
+ALTER TABLE clients DROP COLUMN post_logout_redirect_uris;
+ALTER TABLE clients DROP COLUMN token_endpoint_auth_method;
\ No newline at end of file
@@ -0,0 +1,6 @@
+ALTER TABLE clients ADD COLUMN token_endpoint_auth_method VARCHAR(32) NOT NULL DEFAULT 'client_secret_basic';
+ALTER TABLE clients ADD COLUMN post_logout_redirect_uris TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE clients ADD COLUMN policy_uri VARCHAR(256);
+ALTER TABLE clients ADD COLUMN tos_uri VARCHAR(256);
+ALTER TABLE clients ADD COLUMN default_max_age INTEGER;
+ALTER TABLE clients ADD COLUMN require_auth_time BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
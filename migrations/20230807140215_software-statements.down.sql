@@ -0,0 +1 @@
+ALTER TABLE clients DROP COLUMN software_statement;
\ No newline at end of file
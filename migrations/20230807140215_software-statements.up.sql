@@ -0,0 +1 @@
+ALTER TABLE clients ADD COLUMN software_statement TEXT;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE client_assertion_jtis;
\ No newline at end of file
@@ -0,0 +1,4 @@
+ALTER TABLE clients DROP COLUMN secrets_hashed;
+
+ALTER TABLE clients RENAME COLUMN registration_token_hash TO registration_token;
+ALTER TABLE clients RENAME COLUMN client_secret_hash TO client_secret;
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE clients RENAME COLUMN client_secret TO client_secret_hash;
+ALTER TABLE clients RENAME COLUMN registration_token TO registration_token_hash;
+
+-- Existing rows are hashed on startup, since SQLite can't compute HMACs.
+ALTER TABLE clients ADD COLUMN secrets_hashed BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- Digests can't be turned back into secrets.
+DELETE FROM sessions;
+DELETE FROM authorization_codes;
+DELETE FROM access_tokens;
+DELETE FROM refresh_tokens;
+DELETE FROM device_codes;
+DELETE FROM pushed_auth_requests;
+
+ALTER TABLE refresh_tokens DROP COLUMN uid_hashed;
+ALTER TABLE access_tokens DROP COLUMN uid_hashed;
+ALTER TABLE sessions DROP COLUMN uid_hashed;
\ No newline at end of file
@@ -0,0 +1,15 @@
+-- Existing rows hold plaintext secrets, and SQLite can't hash them. Codes and pushed requests expire
+-- within minutes, so they're dropped. Sessions and tokens are hashed on startup instead, so that
+-- nobody is logged out and refresh tokens keep working.
+DELETE FROM authorization_codes;
+DELETE FROM device_codes;
+DELETE FROM pushed_auth_requests;
+
+ALTER TABLE sessions ADD COLUMN uid_hashed BOOLEAN NOT NULL DEFAULT TRUE;
+UPDATE sessions SET uid_hashed = FALSE;
+
+ALTER TABLE access_tokens ADD COLUMN uid_hashed BOOLEAN NOT NULL DEFAULT TRUE;
+UPDATE access_tokens SET uid_hashed = FALSE;
+
+ALTER TABLE refresh_tokens ADD COLUMN uid_hashed BOOLEAN NOT NULL DEFAULT TRUE;
+UPDATE refresh_tokens SET uid_hashed = FALSE;
\ No newline at end of file
@@ -0,0 +1,3 @@
+DROP TABLE retired_client_secrets;
+
+ALTER TABLE clients DROP COLUMN client_secret_expires_at;
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- RFC 7591, section 3.2.1: NULL means the secret doesn't expire.
+ALTER TABLE clients ADD COLUMN client_secret_expires_at TIMESTAMP;
+
+-- Replaced secrets keep working until their grace period runs out.
+CREATE TABLE retired_client_secrets (
+    client_id BIGINT NOT NULL REFERENCES clients(id),
+    secret_hash VARCHAR(64) NOT NULL,
+
+    expires TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (client_id, secret_hash)
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE reauth_requests;
\ No newline at end of file
@@ -0,0 +1,6 @@
+CREATE TABLE reauth_requests (
+    uid VARCHAR(64) NOT NULL PRIMARY KEY,
+
+    requested_at TIMESTAMP NOT NULL,
+    expires TIMESTAMP NOT NULL
+);
\ No newline at end of file
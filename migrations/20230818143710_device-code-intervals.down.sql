@@ -0,0 +1 @@
+ALTER TABLE device_codes DROP COLUMN poll_interval;
\ No newline at end of file
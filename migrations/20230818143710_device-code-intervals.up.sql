@@ -0,0 +1,2 @@
+-- RFC 8628, section 3.5: slow_down raises the interval for all later requests.
+ALTER TABLE device_codes ADD COLUMN poll_interval INTEGER NOT NULL DEFAULT 5;
\ No newline at end of file
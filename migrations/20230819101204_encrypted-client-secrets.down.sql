@@ -0,0 +1,2 @@
+ALTER TABLE retired_client_secrets DROP COLUMN secret_encrypted;
+ALTER TABLE clients DROP COLUMN client_secret_encrypted;
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- client_secret_jwt needs the secret itself to check assertions, so it's kept encrypted.
+ALTER TABLE clients ADD COLUMN client_secret_encrypted TEXT;
+ALTER TABLE retired_client_secrets ADD COLUMN secret_encrypted TEXT;
\ No newline at end of file
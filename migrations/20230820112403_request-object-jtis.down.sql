@@ -0,0 +1 @@
+DROP TABLE request_object_jtis;
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE request_object_jtis (
+    client_id BIGINT NOT NULL REFERENCES clients(id),
+    jti VARCHAR(256) NOT NULL,
+
+    expires INTEGER NOT NULL,
+
+    PRIMARY KEY (client_id, jti)
+);
\ No newline at end of file
@@ -5,6 +5,7 @@ use argon2::PasswordHash;
 use argon2::PasswordVerifier;
 use askama::Template;
 use axum::extract::ConnectInfo;
+use axum::extract::Query;
 use axum::response::IntoResponse;
 use axum::response::Redirect;
 use axum::response::Response;
@@ -27,13 +28,24 @@ use super::RedirectQuery;
 struct LoginTemplate {
     base: TemplateBase,
     register_url: String,
+    username: String,
     error: Option<String>,
 }
 
-pub async fn login_view(redir: RedirectQuery, base: TemplateBase) -> impl IntoResponse {
+#[derive(Deserialize)]
+pub struct LoginHintQuery {
+    pub login_hint: Option<String>,
+}
+
+pub async fn login_view(
+    redir: RedirectQuery,
+    base: TemplateBase,
+    hint: Query<LoginHintQuery>,
+) -> impl IntoResponse {
     LoginTemplate {
         base: base.clone(),
         register_url: base.links.register_from(redir.redirect_uri),
+        username: hint.0.login_hint.unwrap_or_default(),
         error: None,
     }
 }
@@ -70,6 +82,7 @@ pub async fn login(
                 base: base.clone(),
                 error: Some("No such user".to_string()),
                 register_url: state.links.register_from(redir.redirect_uri),
+                username: req.username.clone(),
             }
         
             .into_response());
@@ -85,6 +98,7 @@ pub async fn login(
                 base: base.clone(),
                 error: Some("Wrong password".to_string()),
                 register_url: state.links.register_from(redir.redirect_uri),
+                username: req.username.clone(),
             }
         
             .into_response());
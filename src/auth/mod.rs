@@ -15,6 +15,7 @@ mod login;
 pub mod logout;
 mod register;
 pub mod session;
+mod user;
 
 #[derive(Deserialize)]
 pub struct RedirectQuery {
@@ -49,4 +50,8 @@ pub fn router() -> Router<ServerState> {
             get(register::register_view).post(register::register),
         )
         .route("/logout", post(logout::logout))
+        .route(
+            "/user/:username",
+            get(user::user_view).post(user::revoke_grant),
+        )
 }
@@ -29,6 +29,7 @@ pub struct AuthSession {
     pub username: String,
     pub last_ip: SocketAddr,
     pub expires: OffsetDateTime,
+    pub created_at: OffsetDateTime,
 }
 
 pub async fn layer(
@@ -39,10 +40,10 @@ pub async fn layer(
     next: Next<Body>,
 ) -> Result<Response, ApiError> {
     if let Some(session_cookie) = jar.get(AuthSession::COOKIE_NAME) {
-        let sid = session_cookie.value();
+        let sid = crate::util::hash_secret(session_cookie.value());
         if let Some(session_rec) = sqlx::query!(
             "
-            SELECT s.user_id as `user_id:EntityId`, u.username, s.last_ip, s.expires
+            SELECT s.user_id as `user_id:EntityId`, u.username, s.last_ip, s.expires, s.created_at as `created_at:OffsetDateTime`
             FROM sessions s
             INNER JOIN users u ON s.user_id = u.id
             WHERE s.uid = $1
@@ -70,10 +71,11 @@ pub async fn layer(
 
             let session = AuthSession {
                 user_id: session_rec.user_id,
-                sid: sid.to_string(),
+                sid,
                 username: session_rec.username,
                 last_ip: from.0,
                 expires: new_expires,
+                created_at: session_rec.created_at,
             };
 
             request.extensions_mut().insert(session);
@@ -97,7 +99,7 @@ impl AuthSession {
         let uid = crate::util::gen_secret();
         let expires = OffsetDateTime::now_utc() + Duration::minutes(30);
 
-        let uid_q = &uid;
+        let uid_q = crate::util::hash_secret(&uid);
         let from_q = ip.to_string();
 
         sqlx::query!(
@@ -145,6 +147,45 @@ impl AuthSession {
             .finish())
     }
 
+    // Rows from before uids were hashed are hashed on startup, since SQLite can't compute SHA-256.
+    pub async fn hash_legacy_uids(pool: &sqlx::Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let rows = sqlx::query!(
+            "
+            SELECT id, uid
+            FROM sessions
+            WHERE NOT uid_hashed
+            "
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for row in &rows {
+            let uid_q = crate::util::hash_secret(&row.uid);
+
+            sqlx::query!(
+                "
+                UPDATE sessions
+                SET uid = $1, uid_hashed = TRUE
+                WHERE id = $2
+                ",
+                uid_q,
+                row.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        if !rows.is_empty() {
+            tracing::info!("Hashed {} sessions", rows.len());
+        }
+
+        Ok(())
+    }
+
     pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
         loop {
             tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
@@ -185,8 +226,7 @@ impl FromRequestParts<ServerState> for AuthSession {
             let orig_uri = OriginalUri::from_request_parts(parts, state).await.unwrap();
 
             return Err(
-                Redirect::to(state.links.login_from(orig_uri.to_string()).as_str())
-                    .into_response(),
+                Redirect::to(state.links.login_from(orig_uri.to_string()).as_str()).into_response(),
             );
         };
 
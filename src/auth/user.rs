@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+use askama::Template;
+use axum::extract::Path;
+use axum::response::IntoResponse;
+use axum::response::Redirect;
+use axum::Form;
+use serde::Deserialize;
+
+use crate::error::ApiError;
+use crate::model::consent_grants::ConsentGrant;
+use crate::state::ServerState;
+use crate::util::csrf::CsrfNonce;
+use crate::util::id::EntityId;
+use crate::util::scopes::Scopes;
+use crate::util::template::TemplateBase;
+
+use super::session::AuthSession;
+
+struct GrantView {
+    client_id: EntityId,
+    client_name: String,
+    logo_uri: String,
+    scopes: Scopes,
+}
+
+#[derive(Template)]
+#[template(path = "user.html")]
+struct UserTemplate {
+    base: TemplateBase,
+    username: String,
+    grants: Vec<GrantView>,
+}
+
+pub async fn user_view(
+    base: TemplateBase,
+    auth: AuthSession,
+    state: ServerState,
+    Path(username): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    if username != auth.username {
+        return Err(crate::error::not_found().into());
+    }
+
+    let grants = sqlx::query!(
+        "
+        SELECT g.client_id as `client_id:EntityId`, c.client_name, c.logo_uri as `logo_uri:String`, g.scope
+        FROM consent_grants g
+        INNER JOIN clients c ON c.id = g.client_id
+        WHERE g.user_id = $1
+        ORDER BY g.id
+        ",
+        auth.user_id
+    )
+    .fetch_all(&state.pool)
+    .await?
+    .into_iter()
+    .map(|x| GrantView {
+        client_id: x.client_id,
+        client_name: x.client_name,
+        logo_uri: x.logo_uri,
+        scopes: Scopes::from_str(&x.scope).unwrap(),
+    })
+    .collect();
+
+    Ok(UserTemplate {
+        base,
+        username,
+        grants,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct RevokeGrantRequest {
+    pub csrf: CsrfNonce,
+    pub client_id: EntityId,
+}
+
+pub async fn revoke_grant(
+    base: TemplateBase,
+    auth: AuthSession,
+    state: ServerState,
+    Path(username): Path<String>,
+    req: Form<RevokeGrantRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    base.csrf.verify(&req.csrf)?;
+
+    if username != auth.username {
+        return Err(crate::error::not_found().into());
+    }
+
+    if ConsentGrant::revoke(auth.user_id, req.client_id, &state.pool).await? {
+        tracing::info!(
+            "User {} revoked consent for client {}",
+            auth.user_id,
+            req.client_id
+        );
+    }
+
+    Ok(Redirect::to(&state.links.user_page(&username)))
+}
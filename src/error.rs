@@ -5,7 +5,7 @@ use axum::{
 };
 use openidconnect::{
     core::{CoreErrorResponseType, CoreRegisterErrorResponseType},
-    StandardErrorResponse,
+    SigningError, StandardErrorResponse,
 };
 use problemdetails::Problem;
 
@@ -18,7 +18,8 @@ pub enum ApiError {
     CsrfFailure,
     Url(url::ParseError),
     Rsa(rsa::Error),
-    FromAxum(Response),
+    Signing(SigningError),
+    FromAxum(Box<Response>),
 }
 
 macro_rules! from_err {
@@ -41,6 +42,7 @@ from_err!(StandardErrorResponse<CoreErrorResponseType>, OauthToken);
 from_err!(password_hash::Error, PasswordHash);
 from_err!(url::ParseError, Url);
 from_err!(rsa::Error, Rsa);
+from_err!(SigningError, Signing);
 
 pub trait IntoApiResult {
     type Value;
@@ -52,7 +54,7 @@ impl<V, E: IntoResponse> IntoApiResult for Result<V, E> {
     type Value = V;
 
     fn into_api(self) -> Result<Self::Value, ApiError> {
-        self.map_err(|x| ApiError::FromAxum(x.into_response()))
+        self.map_err(|x| ApiError::FromAxum(Box::new(x.into_response())))
     }
 }
 
@@ -83,7 +85,11 @@ impl IntoResponse for ApiError {
                 .with_type("https://basique.top/mini-oidc/error/rsa")
                 .with_title("RSA error")
                 .into_response(),
-            ApiError::FromAxum(res) => res,
+            ApiError::Signing(_) => problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .with_type("https://basique.top/mini-oidc/error/signing")
+                .with_title("Signing error")
+                .into_response(),
+            ApiError::FromAxum(res) => *res,
         }
     }
 }
@@ -93,3 +99,7 @@ pub fn not_found() -> Problem {
         .with_type("about:blank")
         .with_title("Not Found")
 }
+
+pub fn oauth_error(error: CoreErrorResponseType, description: Option<&str>) -> ApiError {
+    StandardErrorResponse::new(error, description.map(|x| x.to_string()), None).into()
+}
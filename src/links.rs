@@ -11,6 +11,10 @@ pub struct ServerLinks {
     pub issuer: Url,
     pub oauth_authorize: Url,
     pub oauth_token: Url,
+    pub oauth_revoke: Url,
+    pub oauth_introspect: Url,
+    pub oauth_device_authorization: Url,
+    pub oauth_par: Url,
     pub oidc_jwks: Url,
     pub oidc_register: Url,
     pub oidc_userinfo: Url,
@@ -18,6 +22,7 @@ pub struct ServerLinks {
     pub register: Url,
     pub logout: Url,
     pub user: Url,
+    pub device: Url,
 }
 
 impl ServerLinks {
@@ -25,6 +30,10 @@ impl ServerLinks {
         Ok(ServerLinks {
             oauth_authorize: issuer.join("/api/oauth2/auth")?,
             oauth_token: issuer.join("/api/oauth2/token")?,
+            oauth_revoke: issuer.join("/api/oauth2/revoke")?,
+            oauth_introspect: issuer.join("/api/oauth2/introspect")?,
+            oauth_device_authorization: issuer.join("/api/oauth2/device_authorization")?,
+            oauth_par: issuer.join("/api/oauth2/par")?,
             oidc_jwks: issuer.join("/api/oidc/jwks")?,
             oidc_register: issuer.join("/api/oidc/register")?,
             oidc_userinfo: issuer.join("/api/oidc/userinfo")?,
@@ -32,12 +41,17 @@ impl ServerLinks {
             register: issuer.join("/register")?,
             logout: issuer.join("/logout")?,
             user: issuer.join("/user")?,
+            device: issuer.join("/device")?,
             issuer,
         })
     }
 
     pub fn oidc_config_client(&self, client_id: EntityId) -> ClientConfigUrl {
-        ClientConfigUrl::new(format!("{}/api/oidc/config/{}", self.issuer, client_id)).unwrap()
+        ClientConfigUrl::from_url(
+            self.issuer
+                .join(&format!("/api/oidc/config/{}", client_id))
+                .unwrap(),
+        )
     }
 
     pub fn login_from(&self, redirect_to: impl AsRef<str>) -> String {
@@ -49,6 +63,23 @@ impl ServerLinks {
         uri.into()
     }
 
+    pub fn login_with_hint(
+        &self,
+        redirect_to: impl AsRef<str>,
+        login_hint: Option<&str>,
+    ) -> String {
+        let mut uri = self.login.clone();
+
+        uri.query_pairs_mut()
+            .append_pair("redirect_uri", redirect_to.as_ref());
+
+        if let Some(login_hint) = login_hint {
+            uri.query_pairs_mut().append_pair("login_hint", login_hint);
+        }
+
+        uri.into()
+    }
+
     pub fn register_from(&self, redirect_to: impl AsRef<str>) -> String {
         let mut uri = self.register.clone();
 
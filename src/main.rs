@@ -9,7 +9,16 @@ use axum::{
     Router,
 };
 
-use crate::{auth::session::AuthSession, model::auth_codes::AuthorizationCode};
+use crate::{
+    auth::session::AuthSession,
+    model::{
+        access_tokens::AccessToken, auth_codes::AuthorizationCode,
+        client_assertions::ClientAssertion, client_secrets::RetiredClientSecret,
+        device_codes::DeviceCode, pushed_auth_requests::PushedAuthRequest,
+        reauth_requests::ReauthRequest, refresh_tokens::RefreshToken,
+        request_objects::RequestObject,
+    },
+};
 
 pub mod auth;
 pub mod error;
@@ -31,6 +40,14 @@ pub async fn main() -> anyhow::Result<()> {
 
     tokio::spawn(AuthorizationCode::cleanup_job(state.pool.clone()));
     tokio::spawn(AuthSession::cleanup_job(state.pool.clone()));
+    tokio::spawn(AccessToken::cleanup_job(state.pool.clone()));
+    tokio::spawn(RefreshToken::cleanup_job(state.pool.clone()));
+    tokio::spawn(DeviceCode::cleanup_job(state.pool.clone()));
+    tokio::spawn(PushedAuthRequest::cleanup_job(state.pool.clone()));
+    tokio::spawn(ReauthRequest::cleanup_job(state.pool.clone()));
+    tokio::spawn(ClientAssertion::cleanup_job(state.pool.clone()));
+    tokio::spawn(RequestObject::cleanup_job(state.pool.clone()));
+    tokio::spawn(RetiredClientSecret::cleanup_job(state.pool.clone()));
 
     async fn log_req(req: Request<Body>, next: Next<Body>) -> Response {
         dbg!(&req);
@@ -9,13 +9,16 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use sqlx::types::Json;
-use sqlx::Sqlite;
+use sqlx::{Sqlite, SqliteConnection};
 use time::{Duration, OffsetDateTime};
 
+use url::Url;
+
 use crate::{
     error::ApiError,
+    model::signing_keys::SigningKey,
     state::ServerState,
-    util::{id::EntityId, scopes::Scopes},
+    util::{id::EntityId, jwt, scopes::Scopes},
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,42 +26,77 @@ pub struct AccessTokenBody {
     pub scope: Scopes,
 }
 
+#[derive(Serialize, Deserialize, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum AccessTokenFormat {
+    #[default]
+    Opaque,
+    Jwt,
+}
+
+// RFC 9068, section 2.2
+#[derive(Serialize, Deserialize)]
+struct AccessTokenClaims {
+    iss: Url,
+    exp: i64,
+    aud: String,
+    sub: String,
+    client_id: EntityId,
+    iat: i64,
+    jti: String,
+    scope: Scopes,
+}
+
 pub struct AccessToken {
     pub uid: String,
-    pub user_id: EntityId,
+    pub format: AccessTokenFormat,
+    pub user_id: Option<EntityId>,
     pub client_id: EntityId,
+    pub family_id: Option<EntityId>,
     pub expires: OffsetDateTime,
 
     pub body: AccessTokenBody,
 }
 
 impl AccessToken {
-    pub async fn get<'e, E>(uid: &str, executor: E) -> Result<Option<AccessToken>, ApiError>
-    where
-        E: sqlx::Executor<'e, Database = Sqlite>,
-    {
+    pub const LIFETIME: Duration = Duration::minutes(30);
+
+    pub async fn get(
+        uid: &str,
+        pool: &sqlx::Pool<Sqlite>,
+    ) -> Result<Option<AccessToken>, ApiError> {
+        if uid.contains('.') {
+            return AccessToken::get_jwt(uid, pool).await;
+        }
+
+        let uid_q = crate::util::hash_secret(uid);
+
         Ok(sqlx::query!(
             "
-            SELECT uid as `uid:String`, user_id as `user_id:EntityId`, client_id as `client_id:EntityId`, body as `body:Json<AccessTokenBody>`, expires as `expires:OffsetDateTime`
+            SELECT uid as `uid:String`, user_id as `user_id:EntityId`, client_id as `client_id:EntityId`, family_id as `family_id:EntityId`, body as `body:Json<AccessTokenBody>`, expires as `expires:OffsetDateTime`
             FROM access_tokens
             WHERE uid = $1
             ",
-            uid
+            uid_q
         )
-        .fetch_optional(executor)
+        .fetch_optional(pool)
         .await?
         .map(|x| AccessToken {
             uid: x.uid,
+            format: AccessTokenFormat::Opaque,
             user_id: x.user_id,
             client_id: x.client_id,
+            family_id: x.family_id,
             body: x.body.0,
             expires: x.expires
         }))
     }
 
     pub async fn insert<'e, E>(
-        user_id: EntityId,
+        user_id: Option<EntityId>,
         client_id: EntityId,
+        family_id: Option<EntityId>,
         body: AccessTokenBody,
         executor: E,
     ) -> Result<String, ApiError>
@@ -67,20 +105,21 @@ impl AccessToken {
     {
         let uid = crate::util::gen_secret();
 
-        let uid_q = &uid;
+        let uid_q = crate::util::hash_secret(&uid);
         let body_q = Json(body);
-        let expires_q = OffsetDateTime::now_utc() + Duration::minutes(30);
+        let expires_q = OffsetDateTime::now_utc() + AccessToken::LIFETIME;
 
         sqlx::query!(
             "
             INSERT INTO access_tokens
-            (uid, user_id, client_id, body, expires)
+            (uid, user_id, client_id, family_id, body, expires)
             VALUES
-            ($1, $2, $3, $4, $5)
+            ($1, $2, $3, $4, $5, $6)
             ",
             uid_q,
             user_id,
             client_id,
+            family_id,
             body_q,
             expires_q
         )
@@ -90,6 +129,193 @@ impl AccessToken {
         Ok(uid)
     }
 
+    async fn get_jwt(
+        token: &str,
+        pool: &sqlx::Pool<Sqlite>,
+    ) -> Result<Option<AccessToken>, ApiError> {
+        let keys = SigningKey::get_all(pool)
+            .await?
+            .values()
+            .map(|x| x.into_jwk())
+            .collect::<Vec<_>>();
+
+        let Ok((header, claims)) = jwt::verify::<AccessTokenClaims>(token, &keys) else {
+            return Ok(None);
+        };
+
+        if header.typ.as_deref() != Some("at+jwt") {
+            return Ok(None);
+        }
+
+        let jti_q = &claims.jti;
+        let revoked = sqlx::query!(
+            "
+            SELECT EXISTS(
+                SELECT 1 FROM revoked_jtis
+                WHERE jti = $1
+            ) AS `revoked:bool`
+            ",
+            jti_q
+        )
+        .fetch_one(pool)
+        .await?
+        .revoked
+        .unwrap_or(false);
+
+        if revoked {
+            return Ok(None);
+        }
+
+        let Ok(expires) = OffsetDateTime::from_unix_timestamp(claims.exp) else {
+            return Ok(None);
+        };
+
+        if expires <= OffsetDateTime::now_utc() {
+            return Ok(None);
+        }
+
+        let user_id = if claims.sub == claims.client_id.to_string() {
+            None
+        } else {
+            EntityId::try_from(claims.sub.as_str()).ok()
+        };
+
+        Ok(Some(AccessToken {
+            uid: claims.jti,
+            format: AccessTokenFormat::Jwt,
+            user_id,
+            client_id: claims.client_id,
+            family_id: None,
+            expires,
+            body: AccessTokenBody {
+                scope: claims.scope,
+            },
+        }))
+    }
+
+    pub async fn insert_jwt(
+        user_id: Option<EntityId>,
+        client_id: EntityId,
+        family_id: Option<EntityId>,
+        body: AccessTokenBody,
+        issuer: &Url,
+        conn: &mut SqliteConnection,
+    ) -> Result<String, ApiError> {
+        let now = OffsetDateTime::now_utc();
+        let key = SigningKey::get_current(&mut *conn).await?;
+
+        let claims = AccessTokenClaims {
+            iss: issuer.clone(),
+            exp: (now + AccessToken::LIFETIME).unix_timestamp(),
+            aud: client_id.to_string(),
+            sub: user_id.unwrap_or(client_id).to_string(),
+            client_id,
+            iat: now.unix_timestamp(),
+            jti: crate::util::gen_secret(),
+            scope: body.scope,
+        };
+
+        // Tracked so the token can be revoked along with its family or grant.
+        let jti_q = &claims.jti;
+        let expires_q = now + AccessToken::LIFETIME;
+
+        sqlx::query!(
+            "
+            INSERT INTO access_token_jtis
+            (jti, family_id, user_id, client_id, expires)
+            VALUES
+            ($1, $2, $3, $4, $5)
+            ",
+            jti_q,
+            family_id,
+            user_id,
+            client_id,
+            expires_q
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(jwt::sign(&claims, &key, Some("at+jwt"))?)
+    }
+
+    pub async fn revoke<'e, E>(&self, executor: E) -> Result<(), ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = &self.uid;
+
+        match self.format {
+            AccessTokenFormat::Opaque => {
+                sqlx::query!(
+                    "
+                    DELETE FROM access_tokens
+                    WHERE uid = $1
+                    ",
+                    uid_q
+                )
+                .execute(executor)
+                .await?;
+            }
+            AccessTokenFormat::Jwt => {
+                let expires_q = self.expires;
+
+                sqlx::query!(
+                    "
+                    INSERT OR IGNORE INTO revoked_jtis
+                    (jti, expires)
+                    VALUES
+                    ($1, $2)
+                    ",
+                    uid_q,
+                    expires_q
+                )
+                .execute(executor)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Rows from before uids were hashed are hashed on startup, since SQLite can't compute SHA-256.
+    pub async fn hash_legacy_uids(pool: &sqlx::Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let rows = sqlx::query!(
+            "
+            SELECT id, uid
+            FROM access_tokens
+            WHERE NOT uid_hashed
+            "
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for row in &rows {
+            let uid_q = crate::util::hash_secret(&row.uid);
+
+            sqlx::query!(
+                "
+                UPDATE access_tokens
+                SET uid = $1, uid_hashed = TRUE
+                WHERE id = $2
+                ",
+                uid_q,
+                row.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        if !rows.is_empty() {
+            tracing::info!("Hashed {} access tokens", rows.len());
+        }
+
+        Ok(())
+    }
+
     pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
         loop {
             tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
@@ -114,6 +340,46 @@ impl AccessToken {
                     tracing::error!("Failed to clean up access tokens: {err}");
                 }
             };
+
+            match sqlx::query!(
+                "
+                DELETE FROM revoked_jtis
+                WHERE expires < $1
+                ",
+                now_q
+            )
+            .execute(&pool)
+            .await
+            {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        tracing::debug!("Cleaned up {} revoked JTIs", res.rows_affected());
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to clean up revoked JTIs: {err}");
+                }
+            };
+
+            match sqlx::query!(
+                "
+                DELETE FROM access_token_jtis
+                WHERE expires < $1
+                ",
+                now_q
+            )
+            .execute(&pool)
+            .await
+            {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        tracing::debug!("Cleaned up {} access token JTIs", res.rows_affected());
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to clean up access token JTIs: {err}");
+                }
+            };
         }
     }
 }
@@ -126,26 +392,32 @@ impl FromRequestParts<ServerState> for AccessToken {
         parts: &mut Parts,
         state: &ServerState,
     ) -> Result<Self, Self::Rejection> {
-        if let Some(header) = TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
-            .await
-            .ok()
+        if let Ok(header) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state).await
         {
             if let Some(token) = AccessToken::get(header.token(), &state.pool)
                 .await
                 .map_err(|x| x.into_response())?
             {
-                return Ok(token);
+                // Expired opaque tokens stick around until cleanup_job runs.
+                if token.expires > OffsetDateTime::now_utc() {
+                    return Ok(token);
+                }
             }
         }
 
-        Err((
-            StatusCode::UNAUTHORIZED,
-            AppendHeaders([(
-                axum::http::header::WWW_AUTHENTICATE,
-                "Bearer error=\"invalid_token\"",
-            )]),
-            "",
-        )
-            .into_response())
+        Err(invalid_token())
     }
 }
+
+pub fn invalid_token() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        AppendHeaders([(
+            axum::http::header::WWW_AUTHENTICATE,
+            "Bearer error=\"invalid_token\"",
+        )]),
+        "",
+    )
+        .into_response()
+}
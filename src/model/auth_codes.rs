@@ -2,10 +2,12 @@ use serde::{Deserialize, Serialize};
 use sqlx::types::Json;
 use sqlx::Sqlite;
 use time::{Duration, OffsetDateTime};
+use url::Url;
 
 use crate::{
     error::ApiError,
-    util::{id::EntityId, scopes::Scopes},
+    model::refresh_tokens::RefreshToken,
+    util::{id::EntityId, pkce::CodeChallenge, scopes::Scopes},
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,12 +15,17 @@ pub struct AuthorizationCodeBody {
     pub scope: Scopes,
     pub state: String,
     pub nonce: Option<String>,
+    pub redirect_uri: Url,
+    pub code_challenge: Option<CodeChallenge>,
+    #[serde(with = "time::serde::timestamp")]
+    pub auth_time: OffsetDateTime,
 }
 
 pub struct AuthorizationCode {
     pub uid: String,
     pub user_id: EntityId,
     pub client_id: EntityId,
+    pub family_id: Option<EntityId>,
     pub expires: OffsetDateTime,
 
     pub body: AuthorizationCodeBody,
@@ -29,13 +36,15 @@ impl AuthorizationCode {
     where
         E: sqlx::Executor<'e, Database = Sqlite>,
     {
+        let uid_q = crate::util::hash_secret(uid);
+
         Ok(sqlx::query!(
             "
-            SELECT uid as `uid:String`, user_id as `user_id:EntityId`, client_id as `client_id:EntityId`, body as `body:Json<AuthorizationCodeBody>`, expires as `expires:OffsetDateTime`
+            SELECT uid as `uid:String`, user_id as `user_id:EntityId`, client_id as `client_id:EntityId`, family_id as `family_id:EntityId`, body as `body:Json<AuthorizationCodeBody>`, expires as `expires:OffsetDateTime`
             FROM authorization_codes
             WHERE uid = $1
             ",
-            uid
+            uid_q
         )
         .fetch_optional(executor)
         .await?
@@ -43,6 +52,7 @@ impl AuthorizationCode {
             uid: x.uid,
             user_id: x.user_id,
             client_id: x.client_id,
+            family_id: x.family_id,
             body: x.body.0,
             expires: x.expires
         }))
@@ -59,7 +69,7 @@ impl AuthorizationCode {
     {
         let uid = crate::util::gen_secret();
 
-        let uid_q = &uid;
+        let uid_q = crate::util::hash_secret(&uid);
         let body_q = Json(body);
         let expires_q = OffsetDateTime::now_utc() + Duration::minutes(2);
 
@@ -82,17 +92,42 @@ impl AuthorizationCode {
         Ok(uid)
     }
 
+    pub async fn redeem<'e, E>(&self, family_id: EntityId, executor: E) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = &self.uid;
+
+        let res = sqlx::query!(
+            "
+            UPDATE authorization_codes
+            SET family_id = $1
+            WHERE uid = $2 AND family_id IS NULL
+            ",
+            family_id,
+            uid_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
     pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
         loop {
             tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
 
+            // Redeemed codes are kept for as long as tokens issued from them can be used, so that
+            // replaying them still revokes those tokens.
             let now_q = OffsetDateTime::now_utc();
+            let redeemed_q = now_q - RefreshToken::LIFETIME;
             match sqlx::query!(
                 "
                 DELETE FROM authorization_codes
-                WHERE expires < $1
+                WHERE expires < $1 AND (family_id IS NULL OR expires < $2)
                 ",
-                now_q
+                now_q,
+                redeemed_q
             )
             .execute(&pool)
             .await
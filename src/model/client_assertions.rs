@@ -0,0 +1,65 @@
+use sqlx::Sqlite;
+use time::{Duration, OffsetDateTime};
+
+use crate::{error::ApiError, util::id::EntityId};
+
+pub struct ClientAssertion;
+
+impl ClientAssertion {
+    // Assertions can't be valid for longer than this, so that jtis don't have to be kept around.
+    pub const MAX_LIFETIME: Duration = Duration::minutes(10);
+
+    // RFC 7523, section 3: a jti can only be used once.
+    pub async fn record_jti<'e, E>(
+        client_id: EntityId,
+        jti: &str,
+        expires: i64,
+        executor: E,
+    ) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let res = sqlx::query!(
+            "
+            INSERT OR IGNORE INTO client_assertion_jtis
+            (client_id, jti, expires)
+            VALUES
+            ($1, $2, $3)
+            ",
+            client_id,
+            jti,
+            expires
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
+
+            let now_q = OffsetDateTime::now_utc().unix_timestamp();
+            match sqlx::query!(
+                "
+                DELETE FROM client_assertion_jtis
+                WHERE expires < $1
+                ",
+                now_q
+            )
+            .execute(&pool)
+            .await
+            {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        tracing::debug!("Cleaned up {} client assertion jtis", res.rows_affected());
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to clean up client assertion jtis: {err}");
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use sqlx::Sqlite;
+use time::{Duration, OffsetDateTime};
+
+use crate::{error::ApiError, util::id::EntityId};
+
+pub struct RetiredClientSecret {
+    pub secret_hash: String,
+    pub secret_encrypted: Option<String>,
+    pub expires: OffsetDateTime,
+}
+
+impl RetiredClientSecret {
+    pub const DEFAULT_GRACE_PERIOD: Duration = Duration::days(1);
+    pub const MAX_GRACE_PERIOD: Duration = Duration::days(30);
+
+    pub async fn list<'e, E>(
+        client_id: EntityId,
+        executor: E,
+    ) -> Result<Vec<RetiredClientSecret>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let now_q = OffsetDateTime::now_utc();
+
+        Ok(sqlx::query!(
+            "
+            SELECT secret_hash, secret_encrypted, expires as `expires:OffsetDateTime`
+            FROM retired_client_secrets
+            WHERE client_id = $1 AND expires > $2
+            ORDER BY expires DESC
+            ",
+            client_id,
+            now_q
+        )
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .map(|x| RetiredClientSecret {
+            secret_hash: x.secret_hash,
+            secret_encrypted: x.secret_encrypted,
+            expires: x.expires,
+        })
+        .collect())
+    }
+
+    pub async fn insert<'e, E>(
+        client_id: EntityId,
+        secret_hash: &str,
+        secret_encrypted: Option<&str>,
+        expires: OffsetDateTime,
+        executor: E,
+    ) -> Result<(), ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        sqlx::query!(
+            "
+            INSERT OR REPLACE INTO retired_client_secrets
+            (client_id, secret_hash, secret_encrypted, expires)
+            VALUES
+            ($1, $2, $3, $4)
+            ",
+            client_id,
+            secret_hash,
+            secret_encrypted,
+            expires
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
+
+            let now_q = OffsetDateTime::now_utc();
+            match sqlx::query!(
+                "
+                DELETE FROM retired_client_secrets
+                WHERE expires < $1
+                ",
+                now_q
+            )
+            .execute(&pool)
+            .await
+            {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        tracing::debug!(
+                            "Cleaned up {} retired client secrets",
+                            res.rows_affected()
+                        );
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to clean up retired client secrets: {err}");
+                }
+            };
+        }
+    }
+}
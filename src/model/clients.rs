@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use openidconnect::core::{
+    CoreApplicationType, CoreClientAuthMethod, CoreGrantType, CoreJsonWebKey, CoreJsonWebKeySet,
+    CoreResponseType,
+};
+use openidconnect::reqwest::async_http_client;
+use openidconnect::{JsonWebKey, JsonWebKeySetUrl, RequestUrl, ResponseTypes};
+use sqlx::types::Json;
+use sqlx::Sqlite;
+use time::{Duration, OffsetDateTime};
+use url::Url;
+
+use crate::{
+    error::ApiError,
+    model::{access_tokens::AccessTokenFormat, client_secrets::RetiredClientSecret},
+    util::{id::EntityId, redirect_uri, scopes::Scopes, secret_hash::SecretHasher},
+};
+
+const JWKS_FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const JWKS_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+// When the keys were fetched, the jwks_uri they came from, and the keys.
+type CachedKeys = (Instant, Url, Vec<CoreJsonWebKey>);
+
+lazy_static::lazy_static! {
+    // Keys fetched from jwks_uri, so authorize and token requests don't each hit the client.
+    // Entries remember the URI they came from, so a fetch that finishes after the client changed
+    // its jwks_uri isn't used.
+    static ref JWKS_CACHE: Mutex<HashMap<EntityId, CachedKeys>> =
+        Mutex::new(HashMap::new());
+}
+
+pub struct Client {
+    pub id: EntityId,
+    pub client_name: String,
+    pub app_type: CoreApplicationType,
+    pub client_uri: Option<Url>,
+    pub logo_uri: Url,
+    pub policy_uri: Option<Url>,
+    pub tos_uri: Option<Url>,
+    pub registration_token_hash: String,
+    pub client_secret_hash: Option<String>,
+    pub client_secret_encrypted: Option<String>,
+    pub client_secret_expires_at: Option<OffsetDateTime>,
+    pub token_endpoint_auth_method: CoreClientAuthMethod,
+    pub require_pkce: bool,
+    pub require_par: bool,
+    pub grant_types: Vec<CoreGrantType>,
+    pub response_types: Vec<ResponseTypes<CoreResponseType>>,
+    pub scope: Option<Scopes>,
+    pub access_token_format: AccessTokenFormat,
+    pub jwks: Option<CoreJsonWebKeySet>,
+    pub jwks_uri: Option<Url>,
+    pub request_uris: Vec<RequestUrl>,
+    pub post_logout_redirect_uris: Vec<Url>,
+    pub default_max_age: Option<Duration>,
+    pub require_auth_time: bool,
+    pub software_statement: Option<String>,
+}
+
+impl Client {
+    pub async fn get<'e, E>(id: EntityId, executor: E) -> Result<Option<Client>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        Ok(sqlx::query!(
+            "
+            SELECT id as `id:EntityId`, client_name, app_type, client_uri, logo_uri, policy_uri, tos_uri, registration_token_hash, client_secret_hash, client_secret_encrypted, client_secret_expires_at as `client_secret_expires_at:OffsetDateTime`, token_endpoint_auth_method, require_pkce, require_par, grant_types as `grant_types:Json<Vec<CoreGrantType>>`, response_types as `response_types:Json<Vec<ResponseTypes<CoreResponseType>>>`, scope, access_token_format as `access_token_format:AccessTokenFormat`, jwks as `jwks:Json<CoreJsonWebKeySet>`, jwks_uri, request_uris as `request_uris:Json<Vec<RequestUrl>>`, post_logout_redirect_uris as `post_logout_redirect_uris:Json<Vec<Url>>`, default_max_age, require_auth_time, software_statement
+            FROM clients
+            WHERE id = $1
+            ",
+            id
+        )
+        .fetch_optional(executor)
+        .await?
+        .map(|x| Client {
+            id: x.id,
+            client_name: x.client_name,
+            app_type: serde_json::from_value(serde_json::Value::String(x.app_type)).unwrap(),
+            client_uri: x.client_uri.and_then(|x| Url::parse(&x).ok()),
+            logo_uri: Url::parse(&x.logo_uri).unwrap(),
+            policy_uri: x.policy_uri.and_then(|x| Url::parse(&x).ok()),
+            tos_uri: x.tos_uri.and_then(|x| Url::parse(&x).ok()),
+            registration_token_hash: x.registration_token_hash,
+            client_secret_hash: x.client_secret_hash,
+            client_secret_encrypted: x.client_secret_encrypted,
+            client_secret_expires_at: x.client_secret_expires_at,
+            token_endpoint_auth_method: serde_json::from_value(serde_json::Value::String(
+                x.token_endpoint_auth_method,
+            ))
+            .unwrap(),
+            require_pkce: x.require_pkce,
+            require_par: x.require_par,
+            grant_types: x.grant_types.0,
+            response_types: x.response_types.0,
+            scope: x.scope.map(|x| Scopes::from_str(&x).unwrap()),
+            access_token_format: x.access_token_format,
+            jwks: x.jwks.map(|x| x.0),
+            jwks_uri: x.jwks_uri.and_then(|x| Url::parse(&x).ok()),
+            request_uris: x.request_uris.0,
+            post_logout_redirect_uris: x.post_logout_redirect_uris.0,
+            default_max_age: x.default_max_age.map(Duration::seconds),
+            require_auth_time: x.require_auth_time,
+            software_statement: x.software_statement,
+        }))
+    }
+
+    pub fn secret_expired(&self) -> bool {
+        self.client_secret_expires_at
+            .is_some_and(|x| x <= OffsetDateTime::now_utc())
+    }
+
+    // Secrets replaced by rotate_secret stay valid until their grace period runs out.
+    pub async fn verify_secret<'e, E>(
+        &self,
+        secret: &str,
+        hasher: &SecretHasher,
+        executor: E,
+    ) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let Some(secret_hash) = &self.client_secret_hash else {
+            return Ok(false);
+        };
+
+        if !self.secret_expired() && hasher.verify(secret, secret_hash) {
+            return Ok(true);
+        }
+
+        Ok(RetiredClientSecret::list(self.id, executor)
+            .await?
+            .iter()
+            .any(|x| hasher.verify(secret, &x.secret_hash)))
+    }
+
+    // client_secret_jwt needs the secret itself to check assertions, so it's also kept encrypted.
+    pub fn encrypt_secret(
+        id: EntityId,
+        auth_method: &CoreClientAuthMethod,
+        secret: &str,
+        hasher: &SecretHasher,
+    ) -> Option<String> {
+        match auth_method {
+            CoreClientAuthMethod::ClientSecretJwt => Some(hasher.encrypt(secret, &id.to_string())),
+            _ => None,
+        }
+    }
+
+    // RFC 7523, section 2.2: client_secret_jwt assertions are signed with the secret as an HMAC key.
+    pub async fn secret_jwt_keys<'e, E>(
+        &self,
+        hasher: &SecretHasher,
+        executor: E,
+    ) -> Result<Vec<CoreJsonWebKey>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let mut encrypted = vec![];
+
+        if !self.secret_expired() {
+            encrypted.extend(self.client_secret_encrypted.clone());
+        }
+
+        encrypted.extend(
+            RetiredClientSecret::list(self.id, executor)
+                .await?
+                .into_iter()
+                .filter_map(|x| x.secret_encrypted),
+        );
+
+        let client_id = self.id.to_string();
+
+        Ok(encrypted
+            .iter()
+            .filter_map(|x| hasher.decrypt(x, &client_id))
+            .map(|x| CoreJsonWebKey::new_symmetric(x.into_bytes()))
+            .collect())
+    }
+
+    pub async fn rotate_secret(
+        &self,
+        grace_period: Duration,
+        expires_at: Option<OffsetDateTime>,
+        hasher: &SecretHasher,
+        pool: &sqlx::Pool<Sqlite>,
+    ) -> Result<String, ApiError> {
+        let mut tx = pool.begin().await?;
+
+        if let Some(old_hash) = &self.client_secret_hash {
+            let mut old_expires = OffsetDateTime::now_utc() + grace_period;
+
+            if let Some(expires_at) = self.client_secret_expires_at {
+                old_expires = old_expires.min(expires_at);
+            }
+
+            if old_expires > OffsetDateTime::now_utc() {
+                RetiredClientSecret::insert(
+                    self.id,
+                    old_hash,
+                    self.client_secret_encrypted.as_deref(),
+                    old_expires,
+                    &mut *tx,
+                )
+                .await?;
+            }
+        }
+
+        let secret = crate::util::gen_secret();
+        let secret_q = hasher.hash(&secret);
+        let encrypted_q =
+            Client::encrypt_secret(self.id, &self.token_endpoint_auth_method, &secret, hasher);
+
+        sqlx::query!(
+            "
+            UPDATE clients
+            SET client_secret_hash = $1, client_secret_encrypted = $2, client_secret_expires_at = $3
+            WHERE id = $4
+            ",
+            secret_q,
+            encrypted_q,
+            expires_at,
+            self.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(secret)
+    }
+
+    pub fn verify_registration_token(&self, token: &str, hasher: &SecretHasher) -> bool {
+        hasher.verify(token, &self.registration_token_hash)
+    }
+
+    // Runs on startup rather than in the migration that added hashing, since the HMAC key isn't
+    // available to SQL. Each plaintext secret is overwritten by its hash in the same statement, and
+    // all rows are done in one transaction, so no plaintext outlives it.
+    pub async fn hash_legacy_secrets(
+        pool: &sqlx::Pool<Sqlite>,
+        hasher: &SecretHasher,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let clients = sqlx::query!(
+            "
+            SELECT id as `id:EntityId`, client_secret_hash, registration_token_hash, token_endpoint_auth_method
+            FROM clients
+            WHERE NOT secrets_hashed
+            "
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for client in &clients {
+            let auth_method: CoreClientAuthMethod = serde_json::from_value(
+                serde_json::Value::String(client.token_endpoint_auth_method.clone()),
+            )
+            .unwrap();
+
+            let secret_q = client.client_secret_hash.as_deref().map(|x| hasher.hash(x));
+            let encrypted_q = client
+                .client_secret_hash
+                .as_deref()
+                .and_then(|x| Client::encrypt_secret(client.id, &auth_method, x, hasher));
+            let reg_token_q = hasher.hash(&client.registration_token_hash);
+
+            sqlx::query!(
+                "
+                UPDATE clients
+                SET client_secret_hash = $1, client_secret_encrypted = $2, registration_token_hash = $3, secrets_hashed = TRUE
+                WHERE id = $4
+                ",
+                secret_q,
+                encrypted_q,
+                reg_token_q,
+                client.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        if !clients.is_empty() {
+            tracing::info!("Hashed secrets of {} clients", clients.len());
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete(id: EntityId, pool: &sqlx::Pool<Sqlite>) -> Result<(), ApiError> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM client_redirect_uris
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM client_contacts
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM authorization_codes
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM access_tokens
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM refresh_tokens
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM device_codes
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM pushed_auth_requests
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM consent_grants
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM client_assertion_jtis
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM request_object_jtis
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM retired_client_secrets
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            INSERT OR IGNORE INTO revoked_jtis
+            (jti, expires)
+            SELECT jti, expires FROM access_token_jtis
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM access_token_jtis
+            WHERE client_id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM clients
+            WHERE id = $1
+            ",
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Client::forget_keys(id);
+
+        Ok(())
+    }
+
+    pub async fn keys(&self) -> Option<Vec<CoreJsonWebKey>> {
+        if let Some(jwks) = &self.jwks {
+            return Some(jwks.keys().clone());
+        }
+
+        let jwks_uri = JsonWebKeySetUrl::from_url(self.jwks_uri.clone()?);
+
+        if let Some((fetched, uri, keys)) = JWKS_CACHE.lock().unwrap().get(&self.id) {
+            if fetched.elapsed() < JWKS_CACHE_TTL && uri == jwks_uri.url() {
+                return Some(keys.clone());
+            }
+        }
+
+        let keys = match tokio::time::timeout(
+            JWKS_FETCH_TIMEOUT,
+            CoreJsonWebKeySet::fetch_async(&jwks_uri, async_http_client),
+        )
+        .await
+        {
+            Ok(Ok(jwks)) => jwks.keys().clone(),
+            Ok(Err(err)) => {
+                tracing::warn!("Failed to fetch JWKS for client {}: {err}", self.id);
+                return None;
+            }
+            Err(_) => {
+                tracing::warn!("Timed out fetching JWKS for client {}", self.id);
+                return None;
+            }
+        };
+
+        let mut cache = JWKS_CACHE.lock().unwrap();
+        cache.retain(|_, (fetched, _, _)| fetched.elapsed() < JWKS_CACHE_TTL);
+        cache.insert(
+            self.id,
+            (Instant::now(), jwks_uri.url().clone(), keys.clone()),
+        );
+
+        Some(keys)
+    }
+
+    // Has to be called whenever a client's keys change, so that old ones stop verifying.
+    pub fn forget_keys(id: EntityId) {
+        JWKS_CACHE.lock().unwrap().remove(&id);
+    }
+
+    pub fn allows_grant(&self, grant_type: &str) -> bool {
+        self.grant_types.iter().any(|x| x.as_ref() == grant_type)
+    }
+
+    pub fn allows_scope(&self, scope: &Scopes) -> bool {
+        match &self.scope {
+            Some(allowed) => scope.iter().all(|x| allowed.contains(x)),
+            None => true,
+        }
+    }
+
+    pub async fn has_redirect_uri<'e, E>(
+        &self,
+        redirect_uri: &Url,
+        executor: E,
+    ) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let native = self.app_type == CoreApplicationType::Native;
+
+        Ok(sqlx::query!(
+            "
+            SELECT redirect_uri
+            FROM client_redirect_uris
+            WHERE client_id = $1
+            ",
+            self.id
+        )
+        .fetch_all(executor)
+        .await?
+        .into_iter()
+        .filter_map(|x| Url::parse(&x.redirect_uri).ok())
+        .any(|x| redirect_uri::matches(&x, redirect_uri, native)))
+    }
+}
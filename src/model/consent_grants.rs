@@ -0,0 +1,168 @@
+use std::str::FromStr;
+
+use sqlx::Sqlite;
+
+use crate::{
+    error::ApiError,
+    util::{id::EntityId, scopes::Scopes},
+};
+
+pub struct ConsentGrant {
+    pub user_id: EntityId,
+    pub client_id: EntityId,
+    pub scope: Scopes,
+}
+
+impl ConsentGrant {
+    pub async fn get<'e, E>(
+        user_id: EntityId,
+        client_id: EntityId,
+        executor: E,
+    ) -> Result<Option<ConsentGrant>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        Ok(sqlx::query!(
+            "
+            SELECT user_id as `user_id:EntityId`, client_id as `client_id:EntityId`, scope
+            FROM consent_grants
+            WHERE user_id = $1 AND client_id = $2
+            ",
+            user_id,
+            client_id
+        )
+        .fetch_optional(executor)
+        .await?
+        .map(|x| ConsentGrant {
+            user_id: x.user_id,
+            client_id: x.client_id,
+            scope: Scopes::from_str(&x.scope).unwrap(),
+        }))
+    }
+
+    pub fn covers(&self, scope: &Scopes) -> bool {
+        scope.iter().all(|x| self.scope.contains(x))
+    }
+
+    pub async fn grant(
+        user_id: EntityId,
+        client_id: EntityId,
+        scope: &Scopes,
+        pool: &sqlx::Pool<Sqlite>,
+    ) -> Result<(), ApiError> {
+        let mut tx = pool.begin().await?;
+
+        let mut granted = ConsentGrant::get(user_id, client_id, &mut *tx)
+            .await?
+            .map(|x| x.scope)
+            .unwrap_or(Scopes(vec![]));
+
+        for x in scope.iter() {
+            if !granted.contains(x) {
+                granted.0.push(x.clone());
+            }
+        }
+
+        let scope_q = granted.to_string();
+
+        sqlx::query!(
+            "
+            INSERT INTO consent_grants
+            (user_id, client_id, scope)
+            VALUES
+            ($1, $2, $3)
+            ON CONFLICT (user_id, client_id) DO UPDATE SET scope = excluded.scope
+            ",
+            user_id,
+            client_id,
+            scope_q
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Revoking consent also revokes everything the client got through it.
+    pub async fn revoke(
+        user_id: EntityId,
+        client_id: EntityId,
+        pool: &sqlx::Pool<Sqlite>,
+    ) -> Result<bool, ApiError> {
+        let mut tx = pool.begin().await?;
+
+        let res = sqlx::query!(
+            "
+            DELETE FROM consent_grants
+            WHERE user_id = $1 AND client_id = $2
+            ",
+            user_id,
+            client_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM authorization_codes
+            WHERE user_id = $1 AND client_id = $2 AND family_id IS NULL
+            ",
+            user_id,
+            client_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM refresh_tokens
+            WHERE user_id = $1 AND client_id = $2
+            ",
+            user_id,
+            client_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM access_tokens
+            WHERE user_id = $1 AND client_id = $2
+            ",
+            user_id,
+            client_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            INSERT OR IGNORE INTO revoked_jtis
+            (jti, expires)
+            SELECT jti, expires FROM access_token_jtis
+            WHERE user_id = $1 AND client_id = $2
+            ",
+            user_id,
+            client_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM access_token_jtis
+            WHERE user_id = $1 AND client_id = $2
+            ",
+            user_id,
+            client_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+}
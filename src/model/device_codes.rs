@@ -0,0 +1,294 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::Sqlite;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    error::ApiError,
+    util::{id::EntityId, scopes::Scopes},
+};
+
+// RFC 8628, section 6.1: no vowels, so that user codes can't spell words.
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceCodeBody {
+    pub scope: Scopes,
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(rename_all = "lowercase")]
+pub enum DeviceCodeStatus {
+    Pending,
+    Approved,
+    Denied,
+    Redeemed,
+}
+
+pub struct DeviceCode {
+    pub uid: String,
+    pub user_code: String,
+    pub client_id: EntityId,
+    pub user_id: Option<EntityId>,
+    pub status: DeviceCodeStatus,
+    pub poll_interval: Duration,
+    pub auth_time: Option<OffsetDateTime>,
+    pub expires: OffsetDateTime,
+
+    pub body: DeviceCodeBody,
+}
+
+impl DeviceCode {
+    pub const LIFETIME: Duration = Duration::minutes(10);
+    pub const POLL_INTERVAL: Duration = Duration::seconds(5);
+    pub const SLOW_DOWN_INCREMENT: Duration = Duration::seconds(5);
+
+    pub async fn get<'e, E>(uid: &str, executor: E) -> Result<Option<DeviceCode>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = crate::util::hash_secret(uid);
+
+        Ok(sqlx::query!(
+            "
+            SELECT uid as `uid:String`, user_code, client_id as `client_id:EntityId`, user_id as `user_id:EntityId`, body as `body:Json<DeviceCodeBody>`, status as `status:DeviceCodeStatus`, poll_interval, auth_time as `auth_time:OffsetDateTime`, expires as `expires:OffsetDateTime`
+            FROM device_codes
+            WHERE uid = $1
+            ",
+            uid_q
+        )
+        .fetch_optional(executor)
+        .await?
+        .map(|x| DeviceCode {
+            uid: x.uid,
+            user_code: x.user_code,
+            client_id: x.client_id,
+            user_id: x.user_id,
+            status: x.status,
+            poll_interval: Duration::seconds(x.poll_interval),
+            auth_time: x.auth_time,
+            body: x.body.0,
+            expires: x.expires
+        }))
+    }
+
+    pub async fn get_by_user_code<'e, E>(
+        user_code: &str,
+        executor: E,
+    ) -> Result<Option<DeviceCode>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let user_code_q = normalize_user_code(user_code);
+
+        Ok(sqlx::query!(
+            "
+            SELECT uid as `uid:String`, user_code, client_id as `client_id:EntityId`, user_id as `user_id:EntityId`, body as `body:Json<DeviceCodeBody>`, status as `status:DeviceCodeStatus`, poll_interval, auth_time as `auth_time:OffsetDateTime`, expires as `expires:OffsetDateTime`
+            FROM device_codes
+            WHERE user_code = $1
+            ",
+            user_code_q
+        )
+        .fetch_optional(executor)
+        .await?
+        .map(|x| DeviceCode {
+            uid: x.uid,
+            user_code: x.user_code,
+            client_id: x.client_id,
+            user_id: x.user_id,
+            status: x.status,
+            poll_interval: Duration::seconds(x.poll_interval),
+            auth_time: x.auth_time,
+            body: x.body.0,
+            expires: x.expires
+        }))
+    }
+
+    pub async fn insert<'e, E>(
+        client_id: EntityId,
+        body: DeviceCodeBody,
+        executor: E,
+    ) -> Result<(String, String), ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid = crate::util::gen_secret();
+        let user_code = gen_user_code();
+
+        let uid_q = crate::util::hash_secret(&uid);
+        let user_code_q = &user_code;
+        let body_q = Json(body);
+        let expires_q = OffsetDateTime::now_utc() + DeviceCode::LIFETIME;
+
+        sqlx::query!(
+            "
+            INSERT INTO device_codes
+            (uid, user_code, client_id, body, expires)
+            VALUES
+            ($1, $2, $3, $4, $5)
+            ",
+            uid_q,
+            user_code_q,
+            client_id,
+            body_q,
+            expires_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok((uid, format_user_code(&user_code)))
+    }
+
+    pub fn display_user_code(&self) -> String {
+        format_user_code(&self.user_code)
+    }
+
+    pub async fn decide<'e, E>(
+        &self,
+        user_id: EntityId,
+        auth_time: OffsetDateTime,
+        approved: bool,
+        executor: E,
+    ) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = &self.uid;
+        let status_q = if approved {
+            DeviceCodeStatus::Approved
+        } else {
+            DeviceCodeStatus::Denied
+        };
+
+        let res = sqlx::query!(
+            "
+            UPDATE device_codes
+            SET status = $1, user_id = $2, auth_time = $3
+            WHERE uid = $4 AND status = 'pending'
+            ",
+            status_q,
+            user_id,
+            auth_time,
+            uid_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    pub async fn poll<'e, E>(&self, executor: E) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = &self.uid;
+        let now_q = OffsetDateTime::now_utc();
+        let threshold_q = now_q - self.poll_interval;
+
+        let res = sqlx::query!(
+            "
+            UPDATE device_codes
+            SET last_polled = $1
+            WHERE uid = $2 AND (last_polled IS NULL OR last_polled <= $3)
+            ",
+            now_q,
+            uid_q,
+            threshold_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    // RFC 8628, section 3.5
+    pub async fn slow_down<'e, E>(&self, executor: E) -> Result<(), ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = &self.uid;
+        let increment_q = DeviceCode::SLOW_DOWN_INCREMENT.whole_seconds();
+
+        sqlx::query!(
+            "
+            UPDATE device_codes
+            SET poll_interval = poll_interval + $1
+            WHERE uid = $2
+            ",
+            increment_q,
+            uid_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn redeem<'e, E>(&self, executor: E) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = &self.uid;
+
+        let res = sqlx::query!(
+            "
+            UPDATE device_codes
+            SET status = 'redeemed'
+            WHERE uid = $1 AND status = 'approved'
+            ",
+            uid_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
+
+            let now_q = OffsetDateTime::now_utc();
+            match sqlx::query!(
+                "
+                DELETE FROM device_codes
+                WHERE expires < $1
+                ",
+                now_q
+            )
+            .execute(&pool)
+            .await
+            {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        tracing::debug!("Cleaned up {} device codes", res.rows_affected());
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to clean up device codes: {err}");
+                }
+            };
+        }
+    }
+}
+
+fn gen_user_code() -> String {
+    let mut rng = rand::thread_rng();
+
+    (0..8)
+        .map(|_| USER_CODE_ALPHABET[rng.gen_range(0..USER_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+fn normalize_user_code(user_code: &str) -> String {
+    user_code
+        .chars()
+        .filter(|x| x.is_ascii_alphabetic())
+        .map(|x| x.to_ascii_uppercase())
+        .collect()
+}
+
+fn format_user_code(user_code: &str) -> String {
+    format!("{}-{}", &user_code[..4], &user_code[4..])
+}
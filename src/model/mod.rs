@@ -1,3 +1,12 @@
 pub mod access_tokens;
 pub mod auth_codes;
+pub mod client_assertions;
+pub mod client_secrets;
+pub mod clients;
+pub mod consent_grants;
+pub mod device_codes;
+pub mod pushed_auth_requests;
+pub mod reauth_requests;
+pub mod refresh_tokens;
+pub mod request_objects;
 pub mod signing_keys;
@@ -0,0 +1,131 @@
+use sqlx::Sqlite;
+use time::{Duration, OffsetDateTime};
+
+use crate::{error::ApiError, util::id::EntityId};
+
+pub struct PushedAuthRequest {
+    pub uid: String,
+    pub client_id: EntityId,
+    pub params: String,
+    pub expires: OffsetDateTime,
+}
+
+impl PushedAuthRequest {
+    pub const LIFETIME: Duration = Duration::seconds(90);
+    pub const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+    pub async fn get<'e, E>(
+        request_uri: &str,
+        executor: E,
+    ) -> Result<Option<PushedAuthRequest>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let Some(uid) = request_uri.strip_prefix(PushedAuthRequest::REQUEST_URI_PREFIX) else {
+            return Ok(None);
+        };
+
+        let uid_q = crate::util::hash_secret(uid);
+
+        Ok(sqlx::query!(
+            "
+            SELECT uid as `uid:String`, client_id as `client_id:EntityId`, params, expires as `expires:OffsetDateTime`
+            FROM pushed_auth_requests
+            WHERE uid = $1
+            ",
+            uid_q
+        )
+        .fetch_optional(executor)
+        .await?
+        .map(|x| PushedAuthRequest {
+            uid: x.uid,
+            client_id: x.client_id,
+            params: x.params,
+            expires: x.expires
+        }))
+    }
+
+    pub async fn insert<'e, E>(
+        client_id: EntityId,
+        params: &str,
+        executor: E,
+    ) -> Result<String, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid = crate::util::gen_secret();
+
+        let uid_q = crate::util::hash_secret(&uid);
+        let expires_q = OffsetDateTime::now_utc() + PushedAuthRequest::LIFETIME;
+
+        sqlx::query!(
+            "
+            INSERT INTO pushed_auth_requests
+            (uid, client_id, params, expires)
+            VALUES
+            ($1, $2, $3, $4)
+            ",
+            uid_q,
+            client_id,
+            params,
+            expires_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(format!("{}{}", PushedAuthRequest::REQUEST_URI_PREFIX, uid))
+    }
+
+    pub async fn consume<'e, E>(request_uri: &str, executor: E) -> Result<(), ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let Some(uid) = request_uri.strip_prefix(PushedAuthRequest::REQUEST_URI_PREFIX) else {
+            return Ok(());
+        };
+
+        let uid_q = crate::util::hash_secret(uid);
+
+        sqlx::query!(
+            "
+            DELETE FROM pushed_auth_requests
+            WHERE uid = $1
+            ",
+            uid_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
+
+            let now_q = OffsetDateTime::now_utc();
+            match sqlx::query!(
+                "
+                DELETE FROM pushed_auth_requests
+                WHERE expires < $1
+                ",
+                now_q
+            )
+            .execute(&pool)
+            .await
+            {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        tracing::debug!(
+                            "Cleaned up {} pushed authorization requests",
+                            res.rows_affected()
+                        );
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to clean up pushed authorization requests: {err}");
+                }
+            };
+        }
+    }
+}
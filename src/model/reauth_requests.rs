@@ -0,0 +1,97 @@
+use std::borrow::Cow;
+
+use axum_extra::extract::cookie::{Cookie, Expiration};
+use sqlx::Sqlite;
+use time::{Duration, OffsetDateTime};
+
+use crate::error::ApiError;
+
+// Remembers that the authorize endpoint sent this browser to log in, so that prompt=login
+// and max_age are satisfied by the new session instead of looping.
+pub struct ReauthRequest;
+
+impl ReauthRequest {
+    pub const COOKIE_NAME: &str = "reauth";
+    pub const LIFETIME: Duration = Duration::minutes(10);
+
+    pub async fn insert<'e, E>(executor: E) -> Result<Cookie<'static>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid = crate::util::gen_secret();
+
+        let uid_q = crate::util::hash_secret(&uid);
+        let requested_at_q = OffsetDateTime::now_utc();
+        let expires_q = requested_at_q + ReauthRequest::LIFETIME;
+
+        sqlx::query!(
+            "
+            INSERT INTO reauth_requests
+            (uid, requested_at, expires)
+            VALUES
+            ($1, $2, $3)
+            ",
+            uid_q,
+            requested_at_q,
+            expires_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(Cookie::build(ReauthRequest::COOKIE_NAME, Cow::Owned(uid))
+            .expires(Expiration::DateTime(expires_q))
+            .path("/")
+            // .secure(true)
+            .http_only(true)
+            .finish())
+    }
+
+    // Each request can only be used once.
+    pub async fn consume<'e, E>(uid: &str, executor: E) -> Result<Option<OffsetDateTime>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = crate::util::hash_secret(uid);
+        let now_q = OffsetDateTime::now_utc();
+
+        Ok(sqlx::query!(
+            "
+            DELETE FROM reauth_requests
+            WHERE uid = $1 AND expires > $2
+            RETURNING requested_at as `requested_at:OffsetDateTime`
+            ",
+            uid_q,
+            now_q
+        )
+        .fetch_optional(executor)
+        .await?
+        .map(|x| x.requested_at))
+    }
+
+    pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
+
+            let now_q = OffsetDateTime::now_utc();
+            match sqlx::query!(
+                "
+                DELETE FROM reauth_requests
+                WHERE expires < $1
+                ",
+                now_q
+            )
+            .execute(&pool)
+            .await
+            {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        tracing::debug!("Cleaned up {} reauth requests", res.rows_affected());
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to clean up reauth requests: {err}");
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::Sqlite;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    error::ApiError,
+    util::{id::EntityId, scopes::Scopes},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokenBody {
+    pub scope: Scopes,
+}
+
+pub struct RefreshToken {
+    pub uid: String,
+    pub family_id: EntityId,
+    pub user_id: EntityId,
+    pub client_id: EntityId,
+    pub rotated: bool,
+    pub expires: OffsetDateTime,
+
+    pub body: RefreshTokenBody,
+}
+
+impl RefreshToken {
+    pub const LIFETIME: Duration = Duration::days(30);
+
+    pub async fn get<'e, E>(uid: &str, executor: E) -> Result<Option<RefreshToken>, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = crate::util::hash_secret(uid);
+
+        Ok(sqlx::query!(
+            "
+            SELECT uid as `uid:String`, family_id as `family_id:EntityId`, user_id as `user_id:EntityId`, client_id as `client_id:EntityId`, body as `body:Json<RefreshTokenBody>`, rotated, expires as `expires:OffsetDateTime`
+            FROM refresh_tokens
+            WHERE uid = $1
+            ",
+            uid_q
+        )
+        .fetch_optional(executor)
+        .await?
+        .map(|x| RefreshToken {
+            uid: x.uid,
+            family_id: x.family_id,
+            user_id: x.user_id,
+            client_id: x.client_id,
+            rotated: x.rotated,
+            body: x.body.0,
+            expires: x.expires
+        }))
+    }
+
+    pub async fn insert<'e, E>(
+        family_id: EntityId,
+        user_id: EntityId,
+        client_id: EntityId,
+        body: RefreshTokenBody,
+        executor: E,
+    ) -> Result<String, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid = crate::util::gen_secret();
+
+        let uid_q = crate::util::hash_secret(&uid);
+        let body_q = Json(body);
+        let expires_q = OffsetDateTime::now_utc() + RefreshToken::LIFETIME;
+
+        sqlx::query!(
+            "
+            INSERT INTO refresh_tokens
+            (uid, family_id, user_id, client_id, body, expires)
+            VALUES
+            ($1, $2, $3, $4, $5, $6)
+            ",
+            uid_q,
+            family_id,
+            user_id,
+            client_id,
+            body_q,
+            expires_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(uid)
+    }
+
+    pub async fn rotate<'e, E>(&self, executor: E) -> Result<bool, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let uid_q = &self.uid;
+
+        let res = sqlx::query!(
+            "
+            UPDATE refresh_tokens
+            SET rotated = TRUE
+            WHERE uid = $1 AND rotated = FALSE
+            ",
+            uid_q
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    pub async fn revoke_family(
+        family_id: EntityId,
+        pool: &sqlx::Pool<Sqlite>,
+    ) -> Result<(), ApiError> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM refresh_tokens
+            WHERE family_id = $1
+            ",
+            family_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM access_tokens
+            WHERE family_id = $1
+            ",
+            family_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            INSERT OR IGNORE INTO revoked_jtis
+            (jti, expires)
+            SELECT jti, expires FROM access_token_jtis
+            WHERE family_id = $1
+            ",
+            family_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "
+            DELETE FROM access_token_jtis
+            WHERE family_id = $1
+            ",
+            family_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Rows from before uids were hashed are hashed on startup, since SQLite can't compute SHA-256.
+    pub async fn hash_legacy_uids(pool: &sqlx::Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let rows = sqlx::query!(
+            "
+            SELECT id, uid
+            FROM refresh_tokens
+            WHERE NOT uid_hashed
+            "
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for row in &rows {
+            let uid_q = crate::util::hash_secret(&row.uid);
+
+            sqlx::query!(
+                "
+                UPDATE refresh_tokens
+                SET uid = $1, uid_hashed = TRUE
+                WHERE id = $2
+                ",
+                uid_q,
+                row.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        if !rows.is_empty() {
+            tracing::info!("Hashed {} refresh tokens", rows.len());
+        }
+
+        Ok(())
+    }
+
+    pub async fn cleanup_job(pool: sqlx::Pool<Sqlite>) {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(5 * 60)).await;
+
+            let now_q = OffsetDateTime::now_utc();
+            match sqlx::query!(
+                "
+                DELETE FROM refresh_tokens
+                WHERE expires < $1
+                ",
+                now_q
+            )
+            .execute(&pool)
+            .await
+            {
+                Ok(res) => {
+                    if res.rows_affected() > 0 {
+                        tracing::debug!("Cleaned up {} refresh tokens", res.rows_affected());
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to clean up refresh tokens: {err}");
+                }
+            };
+        }
+    }
+}
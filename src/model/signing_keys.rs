@@ -39,6 +39,30 @@ impl SigningKey {
         .collect::<HashMap<_, _>>())
     }
 
+    pub async fn get_current<'e, E>(executor: E) -> Result<SigningKey, ApiError>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let x = sqlx::query!(
+            "
+                SELECT id as `id:EntityId`, pem_body
+                FROM jwt_keys
+                ORDER BY created_at DESC
+                LIMIT 1
+                "
+        )
+        .fetch_one(executor)
+        .await?;
+
+        let key = CoreRsaPrivateSigningKey::from_pem(
+            &x.pem_body,
+            Some(JsonWebKeyId::new(x.id.to_string())),
+        )
+        .unwrap();
+
+        Ok(SigningKey { id: x.id, key })
+    }
+
     pub fn into_jwk(&self) -> CoreJsonWebKey {
         self.key.as_verification_key()
     }
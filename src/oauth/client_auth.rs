@@ -0,0 +1,230 @@
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{FromRequest, FromRequestParts},
+    headers::{authorization::Basic, Authorization},
+    http::{header::CONTENT_TYPE, request::Parts, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    TypedHeader,
+};
+use openidconnect::core::{CoreClientAuthMethod, CoreErrorResponseType, CoreJsonWebKey};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::{
+    error::{oauth_error, ApiError},
+    model::{client_assertions::ClientAssertion, clients::Client},
+    state::ServerState,
+    util::{id::EntityId, jwt},
+};
+
+// RFC 7523, section 2.2
+const JWT_BEARER: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+#[derive(Deserialize, Default, Clone)]
+pub struct ClientCredentials {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub client_assertion_type: Option<String>,
+    pub client_assertion: Option<String>,
+}
+
+// Credentials can be sent in the form body, which handlers still need to read afterwards.
+pub async fn layer(request: Request<Body>, next: Next<Body>) -> Response {
+    let is_form = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.starts_with("application/x-www-form-urlencoded"));
+
+    if !is_form {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+
+    let body = match Bytes::from_request(Request::new(body), &()).await {
+        Ok(body) => body,
+        Err(err) => return err.into_response(),
+    };
+
+    if let Ok(credentials) = serde_urlencoded::from_bytes::<ClientCredentials>(&body) {
+        parts.extensions.insert(credentials);
+    }
+
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+pub struct AuthenticatedClient(pub Client);
+
+enum Credential<'a> {
+    Basic(&'a str),
+    Post(&'a str),
+    Assertion(&'a str),
+    None,
+}
+
+fn invalid_client() -> ApiError {
+    oauth_error(CoreErrorResponseType::InvalidClient, None)
+}
+
+#[async_trait]
+impl FromRequestParts<ServerState> for AuthenticatedClient {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let basic = TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state)
+            .await
+            .ok();
+        let form = parts
+            .extensions
+            .get::<ClientCredentials>()
+            .cloned()
+            .unwrap_or_default();
+
+        let assertion = match (&form.client_assertion_type, &form.client_assertion) {
+            (None, None) => None,
+            (Some(assertion_type), Some(assertion)) if assertion_type == JWT_BEARER => {
+                Some(assertion.as_str())
+            }
+            _ => return Err(invalid_client()),
+        };
+
+        // RFC 6749, section 2.3: only one authentication method can be used at a time.
+        let (client_id, credential) = match (&basic, &form.client_secret, assertion) {
+            (Some(basic), None, None) => (
+                basic.username().to_string(),
+                Credential::Basic(basic.password()),
+            ),
+            (None, Some(secret), None) => (
+                form.client_id.clone().ok_or_else(invalid_client)?,
+                Credential::Post(secret),
+            ),
+            (None, None, Some(assertion)) => {
+                let (_, claims) = jwt::decode_unverified::<Map<String, Value>>(assertion)
+                    .map_err(|_| invalid_client())?;
+
+                let Some(Value::String(sub)) = claims.get("sub") else {
+                    return Err(invalid_client());
+                };
+
+                (sub.clone(), Credential::Assertion(assertion))
+            }
+            (None, None, None) => (
+                form.client_id.clone().ok_or_else(invalid_client)?,
+                Credential::None,
+            ),
+            _ => return Err(invalid_client()),
+        };
+
+        if form.client_id.as_ref().is_some_and(|x| *x != client_id) {
+            return Err(invalid_client());
+        }
+
+        let Ok(client_id) = EntityId::try_from(client_id.as_str()) else {
+            return Err(invalid_client());
+        };
+
+        let Some(client) = Client::get(client_id, &state.pool).await? else {
+            return Err(invalid_client());
+        };
+
+        let valid = match (&client.token_endpoint_auth_method, credential) {
+            (CoreClientAuthMethod::ClientSecretBasic, Credential::Basic(secret))
+            | (CoreClientAuthMethod::ClientSecretPost, Credential::Post(secret)) => {
+                client
+                    .verify_secret(secret, &state.secret_hasher, &state.pool)
+                    .await?
+            }
+            (CoreClientAuthMethod::ClientSecretJwt, Credential::Assertion(assertion)) => {
+                let keys = client
+                    .secret_jwt_keys(&state.secret_hasher, &state.pool)
+                    .await?;
+
+                !keys.is_empty() && verify_assertion(&client, assertion, &keys, state).await?
+            }
+            (CoreClientAuthMethod::PrivateKeyJwt, Credential::Assertion(assertion)) => {
+                match client.keys().await {
+                    Some(keys) => verify_assertion(&client, assertion, &keys, state).await?,
+                    None => false,
+                }
+            }
+            (CoreClientAuthMethod::None, Credential::None) => true,
+            _ => false,
+        };
+
+        if !valid {
+            return Err(invalid_client());
+        }
+
+        Ok(AuthenticatedClient(client))
+    }
+}
+
+// RFC 7523, section 3
+async fn verify_assertion(
+    client: &Client,
+    assertion: &str,
+    keys: &[CoreJsonWebKey],
+    state: &ServerState,
+) -> Result<bool, ApiError> {
+    let Ok((_, claims)) = jwt::verify::<Map<String, Value>>(assertion, keys) else {
+        return Ok(false);
+    };
+
+    let client_id = client.id.to_string();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    if claims.get("iss").and_then(|x| x.as_str()) != Some(&client_id) {
+        return Ok(false);
+    }
+
+    let audience_matches = |x: &Value| {
+        x.as_str()
+            .and_then(|x| Url::parse(x).ok())
+            .is_some_and(|x| x == state.links.issuer || x == state.links.oauth_token)
+    };
+
+    let audience_valid = match claims.get("aud") {
+        Some(Value::Array(aud)) => aud.iter().any(audience_matches),
+        Some(aud) => audience_matches(aud),
+        None => false,
+    };
+
+    if !audience_valid {
+        return Ok(false);
+    }
+
+    let Some(expires) = claims.get("exp").and_then(|x| x.as_i64()) else {
+        return Ok(false);
+    };
+
+    if expires <= now || expires > now + ClientAssertion::MAX_LIFETIME.whole_seconds() {
+        return Ok(false);
+    }
+
+    if claims
+        .get("nbf")
+        .is_some_and(|x| x.as_i64().map(|x| x > now).unwrap_or(true))
+    {
+        return Ok(false);
+    }
+
+    let Some(jti) = claims.get("jti").and_then(|x| x.as_str()) else {
+        return Ok(false);
+    };
+
+    if !ClientAssertion::record_jti(client.id, jti, expires, &state.pool).await? {
+        tracing::warn!("Client assertion replay detected for client {}", client.id);
+
+        return Ok(false);
+    }
+
+    Ok(true)
+}
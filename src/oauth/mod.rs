@@ -1,18 +1,39 @@
 use axum::{
+    middleware,
     routing::{get, post},
     Router,
 };
 
 use crate::state::ServerState;
 
+mod client_auth;
 mod oauth_authorize;
+mod oauth_device;
+mod oauth_introspect;
+mod oauth_par;
+mod oauth_revoke;
 mod oauth_token;
 
 pub fn router() -> Router<ServerState> {
     Router::new()
+        .route("/api/oauth2/token", post(oauth_token::oauth_token))
+        .route("/api/oauth2/revoke", post(oauth_revoke::oauth_revoke))
+        .route(
+            "/api/oauth2/introspect",
+            post(oauth_introspect::oauth_introspect),
+        )
+        .route(
+            "/api/oauth2/device_authorization",
+            post(oauth_device::device_authorization),
+        )
+        .route("/api/oauth2/par", post(oauth_par::oauth_par))
+        .route_layer(middleware::from_fn(client_auth::layer))
         .route(
             "/api/oauth2/auth",
             get(oauth_authorize::authorization_code).post(oauth_authorize::authorization_code_post),
         )
-        .route("/api/oauth2/token", post(oauth_token::oauth_token))
+        .route(
+            "/device",
+            get(oauth_device::device_view).post(oauth_device::device_post),
+        )
 }
@@ -1,25 +1,46 @@
 use askama::Template;
-use axum::{response::IntoResponse, Form};
+use axum::{
+    extract::OriginalUri,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::CookieJar;
 
-use openidconnect::core::CoreAuthErrorResponseType;
+use openidconnect::core::{CoreAuthErrorResponseType, CoreAuthPrompt, CoreResponseType};
+use openidconnect::Nonce;
 use serde::Deserialize;
+use time::OffsetDateTime;
 
 use crate::{
     auth::session::AuthSession,
     error::ApiError,
-    model::auth_codes::{AuthorizationCode, AuthorizationCodeBody},
+    model::{
+        access_tokens::AccessToken,
+        auth_codes::{AuthorizationCode, AuthorizationCodeBody},
+        clients::Client,
+        consent_grants::ConsentGrant,
+        pushed_auth_requests::PushedAuthRequest,
+        reauth_requests::ReauthRequest,
+    },
     state::ServerState,
     util::{
-        csrf::CsrfNonce, extract::OidcAuthRequestHead, id::EntityId, scopes::Scopes,
+        csrf::CsrfNonce,
+        extract::{OidcAuthRequest, OidcAuthRequestHead},
+        id::EntityId,
+        scopes::Scopes,
         template::TemplateBase,
     },
 };
 
+use super::oauth_token::{issue_access_token, issue_id_token};
+
 #[derive(Template)]
 #[template(path = "authorize.html")]
 struct AuthorizeTemplate {
     client_name: String,
     logo_uri: String,
+    policy_uri: Option<String>,
+    tos_uri: Option<String>,
     scopes: Scopes,
     base: TemplateBase,
 }
@@ -27,37 +48,162 @@ struct AuthorizeTemplate {
 pub async fn authorization_code(
     req: OidcAuthRequestHead,
     base: TemplateBase,
-    _auth: AuthSession,
+    auth: Option<AuthSession>,
     state: ServerState,
-) -> Result<impl IntoResponse, ApiError> {
-    let redirect_uri_q = req.redirect_uri.as_str();
-    let Some(record) = sqlx::query!(
-        "
-        SELECT c.client_name, c.logo_uri as `logo_uri:String`
-        FROM clients c
-        INNER JOIN client_redirect_uris cru ON cru.client_id = c.id
-        WHERE c.id = $1 AND cru.redirect_uri = $2
-        ",
-        req.client_id,
-        redirect_uri_q
-    )
-    .fetch_optional(&state.pool)
-    .await?
-    else {
+    orig_uri: OriginalUri,
+    jar: CookieJar,
+) -> Result<Response, ApiError> {
+    let Some(client) = Client::get(req.client_id, &state.pool).await? else {
         // TODO: display this to user properly.
         return Err(crate::error::not_found()
             .with_detail(format!("'{}' is not a valid client.", req.client_id))
             .into());
     };
 
+    if !client
+        .has_redirect_uri(&req.redirect_uri, &state.pool)
+        .await?
+    {
+        // TODO: display this to user properly.
+        return Err(crate::error::not_found()
+            .with_detail(format!(
+                "'{}' is not a valid redirect URI.",
+                req.redirect_uri
+            ))
+            .into());
+    }
+
+    req.check_par(client.require_par)?;
+
+    let pushed_request_uri = req.pushed_request_uri.clone();
+
     let req = req.next()?;
 
+    req.check_client(&client)?;
+
+    if req.returns(CoreResponseType::Code) {
+        req.code_challenge(client.require_pkce)?;
+    }
+
+    let hinted_user = req.id_token_hint_subject(&state).await?;
+
+    let hint_mismatch = matches!(
+        (&auth, hinted_user),
+        (Some(auth), Some(hinted_user)) if auth.user_id != hinted_user
+    );
+
+    // Set when we sent the user to log in again, so that prompt=login doesn't loop.
+    let reauth_requested = match jar.get(ReauthRequest::COOKIE_NAME) {
+        Some(cookie) => ReauthRequest::consume(cookie.value(), &state.pool).await?,
+        None => None,
+    };
+
+    // Sessions only store created_at to the second.
+    let reauthenticated = matches!(
+        (&auth, reauth_requested),
+        (Some(auth), Some(requested_at))
+            if auth.created_at.unix_timestamp() >= requested_at.unix_timestamp()
+    );
+
+    let login_required = match &auth {
+        None => true,
+        Some(_) if reauthenticated => false,
+        Some(auth) => {
+            req.has_prompt(CoreAuthPrompt::Login)
+                || req.has_prompt(CoreAuthPrompt::SelectAccount)
+                || req
+                    .max_age
+                    .or(client.default_max_age)
+                    .is_some_and(|x| OffsetDateTime::now_utc() - auth.created_at > x)
+                || hint_mismatch
+        }
+    };
+
+    if req.has_prompt(CoreAuthPrompt::None) && (login_required || hint_mismatch) {
+        return Err(req.error(
+            CoreAuthErrorResponseType::LoginRequired,
+            "User needs to log in.",
+        ));
+    }
+
+    let auth = match auth {
+        Some(auth) if !login_required => auth,
+        _ => return login_redirect(&req, hinted_user, &orig_uri, &state).await,
+    };
+
+    if hint_mismatch {
+        return Err(req.error(
+            CoreAuthErrorResponseType::LoginRequired,
+            "Logged in user doesn't match id_token_hint.",
+        ));
+    }
+
+    let consented = !req.has_prompt(CoreAuthPrompt::Consent)
+        && ConsentGrant::get(auth.user_id, req.client_id, &state.pool)
+            .await?
+            .is_some_and(|x| x.covers(&req.scope));
+
+    if consented {
+        if let Some(request_uri) = &pushed_request_uri {
+            PushedAuthRequest::consume(request_uri, &state.pool).await?;
+        }
+
+        req.consume_request_object(&state).await?;
+
+        return respond(&req, &auth, &client, &state).await;
+    }
+
+    if req.has_prompt(CoreAuthPrompt::None) {
+        return Err(req.error(
+            CoreAuthErrorResponseType::ConsentRequired,
+            "User needs to consent.",
+        ));
+    }
+
     Ok(AuthorizeTemplate {
-        client_name: record.client_name,
-        logo_uri: record.logo_uri,
+        client_name: client.client_name,
+        logo_uri: client.logo_uri.to_string(),
+        policy_uri: client.policy_uri.map(|x| x.to_string()),
+        tos_uri: client.tos_uri.map(|x| x.to_string()),
         scopes: req.scope,
         base,
-    })
+    }
+    .into_response())
+}
+
+async fn login_redirect(
+    req: &OidcAuthRequest,
+    hinted_user: Option<EntityId>,
+    orig_uri: &OriginalUri,
+    state: &ServerState,
+) -> Result<Response, ApiError> {
+    let login_hint = match hinted_user {
+        Some(user_id) => sqlx::query!(
+            "
+            SELECT u.username
+            FROM users u
+            WHERE u.id = $1
+            ",
+            user_id
+        )
+        .fetch_optional(&state.pool)
+        .await?
+        .map(|x| x.username),
+        None => req.login_hint.clone(),
+    };
+
+    let mut return_to = state.links.oauth_authorize.clone();
+    return_to.set_query(orig_uri.query());
+
+    Ok((
+        CookieJar::new().add(ReauthRequest::insert(&state.pool).await?),
+        Redirect::to(
+            &state
+                .links
+                .login_with_hint(return_to, login_hint.as_deref()),
+        ),
+    )
+        .into_response())
 }
 
 #[derive(Deserialize)]
@@ -79,26 +225,20 @@ pub async fn authorization_code_post(
     auth: AuthSession,
     state: ServerState,
     req_f: Form<AuthorizeRequest>,
-) -> Result<impl IntoResponse, ApiError> {
+) -> Result<Response, ApiError> {
     base.csrf.verify(&req_f.csrf)?;
 
-    let redirect_uri_q = req.redirect_uri.as_str();
-    let redirect_uri_valid = sqlx::query!(
-        "
-        SELECT EXISTS(
-            SELECT 1 FROM client_redirect_uris
-            WHERE client_id=$1 AND redirect_uri=$2
-        ) AS `valid:bool`
-        ",
-        req.client_id,
-        redirect_uri_q,
-    )
-    .fetch_one(&state.pool)
-    .await?
-    .valid
-    .unwrap_or(false);
+    let Some(client) = Client::get(req.client_id, &state.pool).await? else {
+        // TODO: display this to user properly.
+        return Err(crate::error::not_found()
+            .with_detail(format!("'{}' is not a valid client.", req.client_id))
+            .into());
+    };
 
-    if !redirect_uri_valid {
+    if !client
+        .has_redirect_uri(&req.redirect_uri, &state.pool)
+        .await?
+    {
         // TODO: display this to user properly.
         return Err(crate::error::not_found()
             .with_detail(format!(
@@ -108,8 +248,16 @@ pub async fn authorization_code_post(
             .into());
     }
 
+    req.check_par(client.require_par)?;
+
+    if let Some(request_uri) = &req.pushed_request_uri {
+        PushedAuthRequest::consume(request_uri, &state.pool).await?;
+    }
+
     let req = req.next()?;
 
+    req.consume_request_object(&state).await?;
+
     if let AuthorizeAction::Deny = req_f.action {
         return Err(req.error(
             CoreAuthErrorResponseType::AccessDenied,
@@ -117,17 +265,91 @@ pub async fn authorization_code_post(
         ));
     }
 
-    let code = AuthorizationCode::insert(
-        auth.user_id,
-        req.client_id,
-        AuthorizationCodeBody {
-            scope: req.scope.clone(),
-            state: req.state.clone(),
-            nonce: req.nonce.clone(),
-        },
-        &state.pool,
-    )
-    .await?;
+    req.check_client(&client)?;
+
+    let res = respond(&req, &auth, &client, &state).await?;
+
+    ConsentGrant::grant(auth.user_id, req.client_id, &req.scope, &state.pool).await?;
+
+    Ok(res)
+}
+
+async fn respond(
+    req: &OidcAuthRequest,
+    auth: &AuthSession,
+    client: &Client,
+    state: &ServerState,
+) -> Result<Response, ApiError> {
+    let mut params = vec![];
+
+    let code = if req.returns(CoreResponseType::Code) {
+        let code_challenge = req.code_challenge(client.require_pkce)?;
+
+        let code = AuthorizationCode::insert(
+            auth.user_id,
+            req.client_id,
+            AuthorizationCodeBody {
+                scope: req.scope.clone(),
+                state: req.state.clone(),
+                nonce: req.nonce.clone(),
+                redirect_uri: req.redirect_uri.clone(),
+                code_challenge,
+                auth_time: auth.created_at,
+            },
+            &state.pool,
+        )
+        .await?;
+
+        params.push(("code", code.clone()));
+
+        Some(openidconnect::AuthorizationCode::new(code))
+    } else {
+        None
+    };
+
+    let access_token = if req.returns(CoreResponseType::Token) {
+        let mut conn = state.pool.acquire().await?;
+
+        let access_token = issue_access_token(
+            client,
+            Some(auth.user_id),
+            None,
+            req.scope.clone(),
+            state,
+            &mut conn,
+        )
+        .await?;
+
+        params.push(("access_token", access_token.clone()));
+        params.push(("token_type", "Bearer".to_string()));
+        params.push((
+            "expires_in",
+            AccessToken::LIFETIME.whole_seconds().to_string(),
+        ));
+
+        Some(openidconnect::AccessToken::new(access_token))
+    } else {
+        None
+    };
+
+    if req.returns(CoreResponseType::IdToken) {
+        let mut conn = state.pool.acquire().await?;
+
+        let id_token = issue_id_token(
+            req.client_id,
+            auth.user_id,
+            &req.scope,
+            auth.created_at,
+            req.nonce.clone().map(Nonce::new),
+            access_token.as_ref(),
+            code.as_ref(),
+            state,
+            &mut conn,
+        )
+        .await?;
+
+        params.push(("id_token", id_token.to_string()));
+    }
 
-    Ok(req.proceed(&code))
+    Ok(req.proceed(params).into_response())
 }
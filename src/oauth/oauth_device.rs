@@ -0,0 +1,198 @@
+use askama::Template;
+use axum::{
+    extract::Query,
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use openidconnect::core::{CoreErrorResponseType, CoreGrantType};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::{
+    auth::session::AuthSession,
+    error::{oauth_error, ApiError},
+    model::device_codes::{DeviceCode, DeviceCodeBody, DeviceCodeStatus},
+    state::ServerState,
+    util::{csrf::CsrfNonce, scopes::Scopes, template::TemplateBase},
+};
+
+use super::{client_auth::AuthenticatedClient, oauth_authorize::AuthorizeAction};
+
+#[derive(Deserialize)]
+pub struct DeviceAuthorizationRequest {
+    pub scope: Option<Scopes>,
+}
+
+// RFC 8628, section 3.2
+#[derive(Serialize)]
+pub struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: Url,
+    pub verification_uri_complete: Url,
+    pub expires_in: i64,
+    pub interval: i64,
+}
+
+pub async fn device_authorization(
+    AuthenticatedClient(client): AuthenticatedClient,
+    state: ServerState,
+    req: Form<DeviceAuthorizationRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !client.allows_grant(CoreGrantType::DeviceCode.as_ref()) {
+        return Err(oauth_error(
+            CoreErrorResponseType::UnauthorizedClient,
+            Some("client is not allowed to use this grant type"),
+        ));
+    }
+
+    let scope = req.0.scope.unwrap_or(Scopes(vec![]));
+
+    if !client.allows_scope(&scope) {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidScope,
+            Some("client is not allowed to request this scope"),
+        ));
+    }
+
+    let (device_code, user_code) =
+        DeviceCode::insert(client.id, DeviceCodeBody { scope }, &state.pool).await?;
+
+    let mut verification_uri_complete = state.links.device.clone();
+    verification_uri_complete
+        .query_pairs_mut()
+        .append_pair("user_code", &user_code);
+
+    Ok(Json(DeviceAuthorizationResponse {
+        device_code,
+        user_code,
+        verification_uri: state.links.device.clone(),
+        verification_uri_complete,
+        expires_in: DeviceCode::LIFETIME.whole_seconds(),
+        interval: DeviceCode::POLL_INTERVAL.whole_seconds(),
+    }))
+}
+
+#[derive(Template)]
+#[template(path = "device.html")]
+struct DeviceTemplate {
+    message: Option<String>,
+    error: Option<String>,
+    base: TemplateBase,
+}
+
+#[derive(Template)]
+#[template(path = "device_authorize.html")]
+struct DeviceAuthorizeTemplate {
+    client_name: String,
+    logo_uri: String,
+    user_code: String,
+    scopes: Scopes,
+    base: TemplateBase,
+}
+
+#[derive(Deserialize)]
+pub struct DeviceQuery {
+    pub user_code: Option<String>,
+}
+
+pub async fn device_view(
+    base: TemplateBase,
+    _auth: AuthSession,
+    state: ServerState,
+    query: Query<DeviceQuery>,
+) -> Result<Response, ApiError> {
+    let Some(user_code) = &query.user_code else {
+        return Ok(DeviceTemplate {
+            message: None,
+            error: None,
+            base,
+        }
+        .into_response());
+    };
+
+    let Some(device_code) = pending_device_code(user_code, &state).await? else {
+        return Ok(invalid_user_code(base).into_response());
+    };
+
+    let client = sqlx::query!(
+        "
+        SELECT c.client_name, c.logo_uri as `logo_uri:String`
+        FROM clients c
+        WHERE c.id = $1
+        ",
+        device_code.client_id
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    Ok(DeviceAuthorizeTemplate {
+        client_name: client.client_name,
+        logo_uri: client.logo_uri,
+        user_code: device_code.display_user_code(),
+        scopes: device_code.body.scope,
+        base,
+    }
+    .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct DeviceRequest {
+    pub csrf: CsrfNonce,
+    pub user_code: String,
+    pub action: AuthorizeAction,
+}
+
+pub async fn device_post(
+    base: TemplateBase,
+    auth: AuthSession,
+    state: ServerState,
+    req: Form<DeviceRequest>,
+) -> Result<Response, ApiError> {
+    base.csrf.verify(&req.csrf)?;
+
+    let Some(device_code) = pending_device_code(&req.user_code, &state).await? else {
+        return Ok(invalid_user_code(base).into_response());
+    };
+
+    let approved = matches!(req.action, AuthorizeAction::Allow);
+
+    if !device_code
+        .decide(auth.user_id, auth.created_at, approved, &state.pool)
+        .await?
+    {
+        return Ok(invalid_user_code(base).into_response());
+    }
+
+    let message = if approved {
+        "Device connected. You can return to your device now."
+    } else {
+        "Access denied. You can close this page."
+    };
+
+    Ok(DeviceTemplate {
+        message: Some(message.to_string()),
+        error: None,
+        base,
+    }
+    .into_response())
+}
+
+async fn pending_device_code(
+    user_code: &str,
+    state: &ServerState,
+) -> Result<Option<DeviceCode>, ApiError> {
+    Ok(DeviceCode::get_by_user_code(user_code, &state.pool)
+        .await?
+        .filter(|x| x.status == DeviceCodeStatus::Pending)
+        .filter(|x| x.expires > OffsetDateTime::now_utc()))
+}
+
+fn invalid_user_code(base: TemplateBase) -> impl IntoResponse {
+    DeviceTemplate {
+        message: None,
+        error: Some("This code is invalid or has expired.".to_string()),
+        base,
+    }
+}
@@ -0,0 +1,143 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    headers::{authorization::Basic, Authorization},
+    http::request::Parts,
+    response::IntoResponse,
+    Form, Json, TypedHeader,
+};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use time::OffsetDateTime;
+
+use crate::{
+    error::ApiError,
+    model::{access_tokens::AccessToken, refresh_tokens::RefreshToken},
+    state::ServerState,
+    util::{id::EntityId, scopes::Scopes},
+};
+
+use super::client_auth::AuthenticatedClient;
+
+pub enum IntrospectionCaller {
+    Client(EntityId),
+    ResourceServer,
+}
+
+impl IntrospectionCaller {
+    fn can_see(&self, client_id: EntityId) -> bool {
+        match self {
+            IntrospectionCaller::Client(id) => *id == client_id,
+            IntrospectionCaller::ResourceServer => true,
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<ServerState> for IntrospectionCaller {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        if let Ok(auth) =
+            TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state).await
+        {
+            if state
+                .resource_servers
+                .get(auth.username())
+                .is_some_and(|x| x.as_bytes().ct_eq(auth.password().as_bytes()).into())
+            {
+                return Ok(IntrospectionCaller::ResourceServer);
+            }
+        }
+
+        let AuthenticatedClient(client) =
+            AuthenticatedClient::from_request_parts(parts, state).await?;
+
+        Ok(IntrospectionCaller::Client(client.id))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct IntrospectRequestBody {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct IntrospectResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Scopes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<EntityId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<EntityId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<EntityId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+}
+
+pub async fn oauth_introspect(
+    caller: IntrospectionCaller,
+    state: ServerState,
+    req: Form<IntrospectRequestBody>,
+) -> Result<impl IntoResponse, ApiError> {
+    let res = if req.token_type_hint.as_deref() == Some("refresh_token") {
+        match introspect_refresh_token(&req.token, &state).await? {
+            Some(res) => Some(res),
+            None => introspect_access_token(&req.token, &state).await?,
+        }
+    } else {
+        match introspect_access_token(&req.token, &state).await? {
+            Some(res) => Some(res),
+            None => introspect_refresh_token(&req.token, &state).await?,
+        }
+    };
+
+    Ok(Json(
+        res.filter(|x| x.exp.unwrap_or(0) > OffsetDateTime::now_utc().unix_timestamp())
+            .filter(|x| x.client_id.map(|id| caller.can_see(id)).unwrap_or(false))
+            .unwrap_or_default(),
+    ))
+}
+
+async fn introspect_access_token(
+    uid: &str,
+    state: &ServerState,
+) -> Result<Option<IntrospectResponse>, ApiError> {
+    Ok(AccessToken::get(uid, &state.pool)
+        .await?
+        .map(|token| IntrospectResponse {
+            active: true,
+            scope: Some(token.body.scope),
+            client_id: Some(token.client_id),
+            sub: token.user_id,
+            exp: Some(token.expires.unix_timestamp()),
+            aud: Some(token.client_id),
+            token_type: Some("Bearer"),
+        }))
+}
+
+async fn introspect_refresh_token(
+    uid: &str,
+    state: &ServerState,
+) -> Result<Option<IntrospectResponse>, ApiError> {
+    Ok(RefreshToken::get(uid, &state.pool)
+        .await?
+        .filter(|token| !token.rotated)
+        .map(|token| IntrospectResponse {
+            active: true,
+            scope: Some(token.body.scope),
+            client_id: Some(token.client_id),
+            sub: Some(token.user_id),
+            exp: Some(token.expires.unix_timestamp()),
+            aud: Some(token.client_id),
+            token_type: None,
+        }))
+}
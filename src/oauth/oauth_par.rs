@@ -0,0 +1,89 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use openidconnect::core::CoreErrorResponseType;
+use serde::Serialize;
+
+use crate::{
+    error::{oauth_error, ApiError},
+    model::pushed_auth_requests::PushedAuthRequest,
+    state::ServerState,
+    util::extract::OidcAuthRequest,
+};
+
+use super::client_auth::AuthenticatedClient;
+
+// RFC 9126, section 2.2
+#[derive(Serialize)]
+pub struct ParResponse {
+    pub request_uri: String,
+    pub expires_in: i64,
+}
+
+pub async fn oauth_par(
+    AuthenticatedClient(client): AuthenticatedClient,
+    state: ServerState,
+    body: String,
+) -> Result<impl IntoResponse, ApiError> {
+    let Ok(mut params) = serde_urlencoded::from_str::<Vec<(String, String)>>(&body) else {
+        return Err(oauth_error(CoreErrorResponseType::InvalidRequest, None));
+    };
+
+    if params.iter().any(|(k, _)| k == "request_uri") {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidRequest,
+            Some("request_uri can't be pushed"),
+        ));
+    }
+
+    let client_id = client.id.to_string();
+
+    if params
+        .iter()
+        .any(|(k, v)| k == "client_id" && *v != client_id)
+    {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidRequest,
+            Some("client_id doesn't match the authenticated client"),
+        ));
+    }
+
+    // Client credentials from the body shouldn't end up in the stored request.
+    params.retain(|(k, _)| {
+        !matches!(
+            k.as_str(),
+            "client_id" | "client_secret" | "client_assertion" | "client_assertion_type"
+        )
+    });
+    params.push(("client_id".to_string(), client_id));
+
+    let params = serde_urlencoded::to_string(&params).unwrap();
+
+    let req = match serde_urlencoded::from_str::<OidcAuthRequest>(&params) {
+        Ok(req) => req,
+        Err(err) => {
+            return Err(oauth_error(
+                CoreErrorResponseType::InvalidRequest,
+                Some(&err.to_string()),
+            ))
+        }
+    };
+
+    if !client
+        .has_redirect_uri(&req.redirect_uri, &state.pool)
+        .await?
+    {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidRequest,
+            Some("redirect_uri is not registered for this client"),
+        ));
+    }
+
+    let request_uri = PushedAuthRequest::insert(client.id, &params, &state.pool).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ParResponse {
+            request_uri,
+            expires_in: PushedAuthRequest::LIFETIME.whole_seconds(),
+        }),
+    ))
+}
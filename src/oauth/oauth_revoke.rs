@@ -0,0 +1,59 @@
+use axum::{response::IntoResponse, Form};
+use serde::Deserialize;
+
+use crate::{
+    error::ApiError,
+    model::{access_tokens::AccessToken, clients::Client, refresh_tokens::RefreshToken},
+    state::ServerState,
+};
+
+use super::client_auth::AuthenticatedClient;
+
+#[derive(Deserialize)]
+pub struct RevokeRequestBody {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+}
+
+pub async fn oauth_revoke(
+    AuthenticatedClient(client): AuthenticatedClient,
+    state: ServerState,
+    req: Form<RevokeRequestBody>,
+) -> Result<impl IntoResponse, ApiError> {
+    let check_refresh_first = req.token_type_hint.as_deref() == Some("refresh_token");
+
+    if check_refresh_first && revoke_refresh_token(&req.token, &client, &state).await? {
+        return Ok(());
+    }
+
+    if let Some(token) = AccessToken::get(&req.token, &state.pool).await? {
+        if token.client_id == client.id {
+            token.revoke(&state.pool).await?;
+        }
+
+        return Ok(());
+    }
+
+    if !check_refresh_first {
+        revoke_refresh_token(&req.token, &client, &state).await?;
+    }
+
+    // RFC 7009, section 2.2: invalid tokens don't cause an error response.
+    Ok(())
+}
+
+async fn revoke_refresh_token(
+    uid: &str,
+    client: &Client,
+    state: &ServerState,
+) -> Result<bool, ApiError> {
+    let Some(token) = RefreshToken::get(uid, &state.pool).await? else {
+        return Ok(false);
+    };
+
+    if token.client_id == client.id {
+        RefreshToken::revoke_family(token.family_id, &state.pool).await?;
+    }
+
+    Ok(true)
+}
@@ -1,112 +1,542 @@
-use axum::headers::authorization::Basic;
-use axum::headers::Authorization;
 use axum::response::IntoResponse;
-use axum::{Form, Json, TypedHeader};
-use chrono::{Duration, Utc};
+use axum::{Form, Json};
+use chrono::{Duration, TimeZone, Utc};
 use openidconnect::core::{
-    CoreErrorResponseType, CoreIdToken, CoreIdTokenClaims, CoreIdTokenFields,
+    CoreErrorResponseType, CoreGrantType, CoreIdToken, CoreIdTokenClaims, CoreIdTokenFields,
     CoreJwsSigningAlgorithm, CoreTokenResponse,
 };
-use openidconnect::{
-    Audience, EmptyAdditionalClaims, EmptyExtraTokenFields, IssuerUrl,
-    StandardErrorResponse,
-};
+use openidconnect::{Audience, EmptyAdditionalClaims, EmptyExtraTokenFields, IssuerUrl, Nonce};
 use serde::Deserialize;
+use sqlx::SqliteConnection;
+use url::Url;
 
-use crate::error::ApiError;
-use crate::model::access_tokens::{AccessToken, AccessTokenBody};
+use crate::error::{oauth_error, ApiError};
+use crate::model::access_tokens::{AccessToken, AccessTokenBody, AccessTokenFormat};
 use crate::model::auth_codes::AuthorizationCode;
+use crate::model::clients::Client;
+use crate::model::device_codes::{DeviceCode, DeviceCodeStatus};
+use crate::model::refresh_tokens::{RefreshToken, RefreshTokenBody};
 use crate::model::signing_keys::SigningKey;
+use crate::oauth::client_auth::AuthenticatedClient;
 use crate::oidc::claim_gatherer;
 use crate::state::ServerState;
 use crate::util::id::EntityId;
+use crate::util::scopes::Scopes;
 
 #[derive(Deserialize)]
 pub struct TokenRequestBody {
-    pub code: String,
-    pub redirect_uri: String,
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<Url>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub device_code: Option<String>,
+    pub scope: Option<Scopes>,
 }
 
 pub async fn oauth_token(
-    auth: TypedHeader<Authorization<Basic>>,
+    AuthenticatedClient(client): AuthenticatedClient,
     state: ServerState,
     req: Form<TokenRequestBody>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let Some(flow) = AuthorizationCode::get(&req.code, &state.pool).await? else {
-        return Err(StandardErrorResponse::<CoreErrorResponseType>::new(
-            CoreErrorResponseType::InvalidGrant,
+    match req.grant_type.as_str() {
+        "authorization_code"
+        | "refresh_token"
+        | "client_credentials"
+        | "urn:ietf:params:oauth:grant-type:device_code"
+            if !client.allows_grant(&req.grant_type) =>
+        {
+            Err(oauth_error(
+                CoreErrorResponseType::UnauthorizedClient,
+                Some("client is not allowed to use this grant type"),
+            ))
+        }
+        "authorization_code" => authorization_code_grant(client, req.0, state).await,
+        "refresh_token" => refresh_token_grant(client, req.0, state).await,
+        "client_credentials" => client_credentials_grant(client, req.0, state).await,
+        "urn:ietf:params:oauth:grant-type:device_code" => {
+            device_code_grant(client, req.0, state).await
+        }
+        _ => Err(oauth_error(
+            CoreErrorResponseType::UnsupportedGrantType,
             None,
-            None,
-        )
-        .into());
+        )),
+    }
+}
+
+async fn authorization_code_grant(
+    client: Client,
+    req: TokenRequestBody,
+    state: ServerState,
+) -> Result<Json<CoreTokenResponse>, ApiError> {
+    let client_id = client.id;
+
+    let Some(code) = &req.code else {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidRequest,
+            Some("code is required"),
+        ));
     };
 
-    let Ok(client_id) = EntityId::try_from(auth.username()) else {
-        return Err(StandardErrorResponse::<CoreErrorResponseType>::new(
-            CoreErrorResponseType::InvalidClient,
-            None,
-            None,
-        )
-        .into());
+    let Some(flow) = AuthorizationCode::get(code, &state.pool).await? else {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
     };
-    let client_secret = auth.password();
-
-    let Some(..) = sqlx::query!(
-        "
-        SELECT 1 as `ae`
-        FROM clients
-        WHERE id = $1 AND client_secret = $2
-        ",
-        client_id,
-        client_secret
+
+    if flow.client_id != client_id {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
+    }
+
+    // Checked before expiry, since redeemed codes are kept around to catch late replays.
+    if let Some(family_id) = flow.family_id {
+        return Err(revoke_replayed_code(client_id, family_id, &state).await?);
+    }
+
+    if flow.expires < time::OffsetDateTime::now_utc() {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
+    }
+
+    if req.redirect_uri.as_ref() != Some(&flow.body.redirect_uri) {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidGrant,
+            Some("redirect_uri doesn't match the authorization request"),
+        ));
+    }
+
+    let pkce_valid = match (&flow.body.code_challenge, &req.code_verifier) {
+        (Some(challenge), Some(verifier)) => challenge.verify(verifier),
+        (None, None) => true,
+        _ => false,
+    };
+
+    if !pkce_valid {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidGrant,
+            Some("PKCE verification failed"),
+        ));
+    }
+
+    let family_id = EntityId::generate(&mut rand::thread_rng());
+
+    // The code is only marked redeemed if the tokens are issued, and only one request can do both.
+    let mut tx = state.pool.begin().await?;
+
+    if !flow.redeem(family_id, &mut *tx).await? {
+        tx.rollback().await?;
+
+        // Another request redeemed the code since it was loaded.
+        let family_id = AuthorizationCode::get(code, &state.pool)
+            .await?
+            .and_then(|x| x.family_id);
+
+        return Err(match family_id {
+            Some(family_id) => revoke_replayed_code(client_id, family_id, &state).await?,
+            None => oauth_error(CoreErrorResponseType::InvalidGrant, None),
+        });
+    }
+
+    let res = issue_user_tokens(
+        &client,
+        flow.user_id,
+        family_id,
+        flow.body.scope,
+        flow.body.auth_time,
+        flow.body.nonce.map(Nonce::new),
+        Some(&openidconnect::AuthorizationCode::new(code.clone())),
+        &state,
+        &mut tx,
     )
-    .fetch_optional(&state.pool)
-    .await?
-    else {
-        return Err(StandardErrorResponse::<CoreErrorResponseType>::new(
-            CoreErrorResponseType::InvalidClient,
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(res)
+}
+
+// RFC 6749, section 4.1.2: everything issued from a replayed code is revoked.
+async fn revoke_replayed_code(
+    client_id: EntityId,
+    family_id: EntityId,
+    state: &ServerState,
+) -> Result<ApiError, ApiError> {
+    tracing::warn!(
+        "Authorization code reuse detected for client {}, revoking token family {}",
+        client_id,
+        family_id
+    );
+
+    RefreshToken::revoke_family(family_id, &state.pool).await?;
+
+    Ok(oauth_error(
+        CoreErrorResponseType::InvalidGrant,
+        Some("authorization code was already used"),
+    ))
+}
+
+async fn device_code_grant(
+    client: Client,
+    req: TokenRequestBody,
+    state: ServerState,
+) -> Result<Json<CoreTokenResponse>, ApiError> {
+    let Some(device_code) = &req.device_code else {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidRequest,
+            Some("device_code is required"),
+        ));
+    };
+
+    let Some(flow) = DeviceCode::get(device_code, &state.pool).await? else {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
+    };
+
+    if flow.client_id != client.id {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
+    }
+
+    // RFC 8628, section 3.5
+    if flow.expires < time::OffsetDateTime::now_utc() {
+        return Err(oauth_error(
+            CoreErrorResponseType::Extension("expired_token".to_string()),
             None,
+        ));
+    }
+
+    if !flow.poll(&state.pool).await? {
+        flow.slow_down(&state.pool).await?;
+
+        return Err(oauth_error(
+            CoreErrorResponseType::Extension("slow_down".to_string()),
             None,
+        ));
+    }
+
+    match flow.status {
+        DeviceCodeStatus::Pending => {
+            return Err(oauth_error(
+                CoreErrorResponseType::Extension("authorization_pending".to_string()),
+                None,
+            ))
+        }
+        DeviceCodeStatus::Denied => {
+            return Err(oauth_error(
+                CoreErrorResponseType::Extension("access_denied".to_string()),
+                None,
+            ))
+        }
+        DeviceCodeStatus::Redeemed => {
+            return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None))
+        }
+        DeviceCodeStatus::Approved => {}
+    }
+
+    let (Some(user_id), Some(auth_time)) = (flow.user_id, flow.auth_time) else {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
+    };
+
+    let mut tx = state.pool.begin().await?;
+
+    if !flow.redeem(&mut *tx).await? {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
+    }
+
+    let family_id = EntityId::generate(&mut rand::thread_rng());
+
+    let res = issue_user_tokens(
+        &client,
+        user_id,
+        family_id,
+        flow.body.scope,
+        auth_time,
+        None,
+        None,
+        &state,
+        &mut tx,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(res)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn issue_user_tokens(
+    client: &Client,
+    user_id: EntityId,
+    family_id: EntityId,
+    scope: Scopes,
+    auth_time: time::OffsetDateTime,
+    nonce: Option<Nonce>,
+    code: Option<&openidconnect::AuthorizationCode>,
+    state: &ServerState,
+    conn: &mut SqliteConnection,
+) -> Result<Json<CoreTokenResponse>, ApiError> {
+    let access_token = issue_access_token(
+        client,
+        Some(user_id),
+        Some(family_id),
+        scope.clone(),
+        state,
+        &mut *conn,
+    )
+    .await?;
+
+    let access_token = openidconnect::AccessToken::new(access_token);
+
+    let id_token = issue_id_token(
+        client.id,
+        user_id,
+        &scope,
+        auth_time,
+        nonce,
+        Some(&access_token),
+        code,
+        state,
+        &mut *conn,
+    )
+    .await?;
+
+    // Clients that didn't register the refresh_token grant couldn't use one anyway.
+    let refresh_token = if scope.iter().any(|x| x == "offline_access")
+        && client.allows_grant(CoreGrantType::RefreshToken.as_ref())
+    {
+        Some(
+            RefreshToken::insert(
+                family_id,
+                user_id,
+                client.id,
+                RefreshTokenBody { scope },
+                &mut *conn,
+            )
+            .await?,
         )
-        .into());
+    } else {
+        None
     };
 
+    let mut res = CoreTokenResponse::new(
+        access_token,
+        openidconnect::core::CoreTokenType::Bearer,
+        CoreIdTokenFields::new(Some(id_token), EmptyExtraTokenFields {}),
+    );
+
+    res.set_refresh_token(refresh_token.map(openidconnect::RefreshToken::new));
+
+    Ok(Json(res))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn issue_id_token(
+    client_id: EntityId,
+    user_id: EntityId,
+    scope: &Scopes,
+    auth_time: time::OffsetDateTime,
+    nonce: Option<Nonce>,
+    access_token: Option<&openidconnect::AccessToken>,
+    code: Option<&openidconnect::AuthorizationCode>,
+    state: &ServerState,
+    conn: &mut SqliteConnection,
+) -> Result<CoreIdToken, ApiError> {
     let claims = CoreIdTokenClaims::new(
         IssuerUrl::from_url(state.links.issuer.clone()),
         vec![Audience::new(client_id.to_string())],
         Utc::now() + Duration::minutes(30),
         Utc::now(),
-        claim_gatherer::gather(flow.user_id, &flow.body.scope, &state.pool).await?,
+        claim_gatherer::gather(user_id, scope, &mut *conn).await?,
         EmptyAdditionalClaims {},
-    );
+    )
+    .set_nonce(nonce)
+    // Always included, which also satisfies clients registered with require_auth_time.
+    .set_auth_time(Utc.timestamp_opt(auth_time.unix_timestamp(), 0).single());
 
-    let keys = SigningKey::get_all(&state.pool).await?;
-    let key = keys.values().next().unwrap();
+    let key = SigningKey::get_current(&mut *conn).await?;
 
-    let id_token = CoreIdToken::new(
+    Ok(CoreIdToken::new(
         claims,
         &key.key,
         CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
-        None,
-        Some(&openidconnect::AuthorizationCode::new(req.code.clone())),
+        access_token,
+        code,
     )
-    .unwrap();
+    .unwrap())
+}
 
-    let access_token = AccessToken::insert(
-        flow.user_id,
-        flow.client_id,
-        AccessTokenBody {
-            scope: flow.body.scope,
+async fn refresh_token_grant(
+    client: Client,
+    req: TokenRequestBody,
+    state: ServerState,
+) -> Result<Json<CoreTokenResponse>, ApiError> {
+    let client_id = client.id;
+
+    let Some(refresh_token) = &req.refresh_token else {
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidRequest,
+            Some("refresh_token is required"),
+        ));
+    };
+
+    let Some(old_token) = RefreshToken::get(refresh_token, &state.pool).await? else {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
+    };
+
+    if old_token.client_id != client_id || old_token.expires < time::OffsetDateTime::now_utc() {
+        return Err(oauth_error(CoreErrorResponseType::InvalidGrant, None));
+    }
+
+    // Rotating and issuing the new tokens happen together, so that a failure doesn't leave the
+    // family without a usable refresh token.
+    let mut tx = state.pool.begin().await?;
+
+    if old_token.rotated || !old_token.rotate(&mut *tx).await? {
+        tx.rollback().await?;
+
+        tracing::warn!(
+            "Refresh token reuse detected for client {}, revoking token family {}",
+            client_id,
+            old_token.family_id
+        );
+
+        RefreshToken::revoke_family(old_token.family_id, &state.pool).await?;
+
+        return Err(oauth_error(
+            CoreErrorResponseType::InvalidGrant,
+            Some("refresh token was already used"),
+        ));
+    }
+
+    let scope = match &req.scope {
+        Some(scope) => {
+            if scope.iter().any(|x| !old_token.body.scope.contains(x)) {
+                return Err(oauth_error(CoreErrorResponseType::InvalidScope, None));
+            }
+
+            scope.clone()
+        }
+        None => old_token.body.scope.clone(),
+    };
+
+    let scope_narrowed = scope != old_token.body.scope;
+
+    let access_token = issue_access_token(
+        &client,
+        Some(old_token.user_id),
+        Some(old_token.family_id),
+        scope.clone(),
+        &state,
+        &mut tx,
+    )
+    .await?;
+
+    let new_refresh_token = RefreshToken::insert(
+        old_token.family_id,
+        old_token.user_id,
+        client_id,
+        RefreshTokenBody {
+            scope: old_token.body.scope,
         },
-        &state.pool,
+        &mut *tx,
     )
     .await?;
 
-    let res = CoreTokenResponse::new(
+    tx.commit().await?;
+
+    let mut res = CoreTokenResponse::new(
         openidconnect::AccessToken::new(access_token),
         openidconnect::core::CoreTokenType::Bearer,
-        CoreIdTokenFields::new(Some(id_token), EmptyExtraTokenFields {}),
+        CoreIdTokenFields::new(None, EmptyExtraTokenFields {}),
     );
 
+    res.set_refresh_token(Some(openidconnect::RefreshToken::new(new_refresh_token)));
+
+    if scope_narrowed {
+        res.set_scopes(Some(
+            scope
+                .iter()
+                .map(|x| openidconnect::Scope::new(x.clone()))
+                .collect(),
+        ));
+    }
+
     Ok(Json(res))
 }
+
+async fn client_credentials_grant(
+    client: Client,
+    req: TokenRequestBody,
+    state: ServerState,
+) -> Result<Json<CoreTokenResponse>, ApiError> {
+    // Clients that didn't register a scope get none, and scopes the server stopped allowing since
+    // registration aren't issued.
+    let allowed_scope = Scopes(
+        client
+            .scope
+            .iter()
+            .flat_map(|x| x.iter())
+            .filter(|x| state.registration_policy.allows_scope(x))
+            .cloned()
+            .collect(),
+    );
+
+    let scope = match req.scope {
+        Some(scope) => {
+            if scope.iter().any(|x| !allowed_scope.contains(x)) {
+                return Err(oauth_error(CoreErrorResponseType::InvalidScope, None));
+            }
+
+            scope
+        }
+        None => allowed_scope,
+    };
+
+    let mut conn = state.pool.acquire().await?;
+
+    let access_token =
+        issue_access_token(&client, None, None, scope.clone(), &state, &mut conn).await?;
+
+    let mut res = CoreTokenResponse::new(
+        openidconnect::AccessToken::new(access_token),
+        openidconnect::core::CoreTokenType::Bearer,
+        CoreIdTokenFields::new(None, EmptyExtraTokenFields {}),
+    );
+
+    res.set_scopes(Some(
+        scope
+            .iter()
+            .map(|x| openidconnect::Scope::new(x.clone()))
+            .collect(),
+    ));
+
+    Ok(Json(res))
+}
+
+pub async fn issue_access_token(
+    client: &Client,
+    user_id: Option<EntityId>,
+    family_id: Option<EntityId>,
+    scope: Scopes,
+    state: &ServerState,
+    conn: &mut SqliteConnection,
+) -> Result<String, ApiError> {
+    match client.access_token_format {
+        AccessTokenFormat::Opaque => {
+            AccessToken::insert(
+                user_id,
+                client.id,
+                family_id,
+                AccessTokenBody { scope },
+                conn,
+            )
+            .await
+        }
+        AccessTokenFormat::Jwt => {
+            AccessToken::insert_jwt(
+                user_id,
+                client.id,
+                family_id,
+                AccessTokenBody { scope },
+                &state.links.issuer,
+                conn,
+            )
+            .await
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use openidconnect::{
+    core::{
+        CoreApplicationType, CoreClientAuthMethod, CoreGrantType, CoreJsonWebKey,
+        CoreJsonWebKeyType, CoreJsonWebKeyUse, CoreJweContentEncryptionAlgorithm,
+        CoreJweKeyManagementAlgorithm, CoreJwsSigningAlgorithm, CoreResponseType,
+        CoreSubjectIdentifierType,
+    },
+    registration::{AdditionalClientMetadata, EmptyAdditionalClientRegistrationResponse},
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{model::access_tokens::AccessTokenFormat, util::scopes::Scopes};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ExtraClientMetadata {
+    #[serde(default)]
+    pub require_pkce: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<Scopes>,
+    #[serde(default)]
+    pub access_token_format: AccessTokenFormat,
+    #[serde(default)]
+    pub require_pushed_authorization_requests: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_logout_redirect_uris: Option<Vec<Url>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software_statement: Option<String>,
+}
+
+impl AdditionalClientMetadata for ExtraClientMetadata {}
+
+pub type ClientMetadata = openidconnect::registration::ClientMetadata<
+    ExtraClientMetadata,
+    CoreApplicationType,
+    CoreClientAuthMethod,
+    CoreGrantType,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJweKeyManagementAlgorithm,
+    CoreJwsSigningAlgorithm,
+    CoreJsonWebKeyType,
+    CoreJsonWebKeyUse,
+    CoreJsonWebKey,
+    CoreResponseType,
+    CoreSubjectIdentifierType,
+>;
+
+pub type ClientRegistrationResponse = openidconnect::registration::ClientRegistrationResponse<
+    ExtraClientMetadata,
+    EmptyAdditionalClientRegistrationResponse,
+    CoreApplicationType,
+    CoreClientAuthMethod,
+    CoreGrantType,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJweKeyManagementAlgorithm,
+    CoreJwsSigningAlgorithm,
+    CoreJsonWebKeyType,
+    CoreJsonWebKeyUse,
+    CoreJsonWebKey,
+    CoreResponseType,
+    CoreSubjectIdentifierType,
+>;
@@ -6,9 +6,12 @@ use axum::{
 use crate::state::ServerState;
 
 pub mod claim_gatherer;
-mod oidc_config;
+pub mod client_metadata;
+mod oidc_client_config;
+pub mod oidc_config;
 mod oidc_register;
 mod oidc_userinfo;
+pub mod registration_policy;
 
 pub fn router() -> Router<ServerState> {
     Router::new()
@@ -18,5 +21,15 @@ pub fn router() -> Router<ServerState> {
         )
         .route("/api/oidc/jwks", get(oidc_config::keyset))
         .route("/api/oidc/register", post(oidc_register::register_client))
+        .route(
+            "/api/oidc/config/:client_id",
+            get(oidc_client_config::get_client)
+                .put(oidc_client_config::update_client)
+                .delete(oidc_client_config::delete_client),
+        )
+        .route(
+            "/api/oidc/config/:client_id/secrets",
+            get(oidc_client_config::list_secrets).post(oidc_client_config::rotate_secret),
+        )
         .route("/api/oidc/userinfo", get(oidc_userinfo::userinfo))
 }
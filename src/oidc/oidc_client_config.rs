@@ -0,0 +1,510 @@
+use axum::extract::Path;
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{Json, TypedHeader};
+use chrono::{TimeZone, Utc};
+use openidconnect::core::CoreRegisterErrorResponseType;
+use openidconnect::registration::EmptyAdditionalClientRegistrationResponse;
+use openidconnect::{
+    ClientContactEmail, ClientId, ClientName, ClientSecret, ClientUrl, LocalizedClaim, LogoUrl,
+    PolicyUrl, RedirectUrl, RegistrationAccessToken, StandardErrorResponse, ToSUrl,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json as SqlJson;
+use sqlx::{Connection, SqliteConnection};
+use time::{Duration, OffsetDateTime};
+
+use crate::error::ApiError;
+use crate::links::ServerLinks;
+use crate::model::access_tokens::invalid_token;
+use crate::model::client_secrets::RetiredClientSecret;
+use crate::model::clients::Client;
+use crate::oidc::client_metadata::{
+    ClientMetadata, ClientRegistrationResponse, ExtraClientMetadata,
+};
+use crate::oidc::oidc_register::validate_metadata;
+use crate::state::ServerState;
+use crate::util::id::EntityId;
+
+// Secrets are only stored hashed, so they can only be returned when they are issued.
+pub async fn registration_response(
+    client: &Client,
+    client_secret: Option<&str>,
+    registration_token: Option<&str>,
+    conn: &mut SqliteConnection,
+    links: &ServerLinks,
+) -> Result<ClientRegistrationResponse, ApiError> {
+    let redirect_uris = sqlx::query!(
+        "
+        SELECT redirect_uri
+        FROM client_redirect_uris
+        WHERE client_id = $1
+        ORDER BY rowid
+        ",
+        client.id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .filter_map(|x| RedirectUrl::new(x.redirect_uri).ok())
+    .collect();
+
+    let contacts: Vec<ClientContactEmail> = sqlx::query!(
+        "
+        SELECT email
+        FROM client_contacts
+        WHERE client_id = $1
+        ORDER BY rowid
+        ",
+        client.id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|x| ClientContactEmail::new(x.email))
+    .collect();
+
+    Ok(ClientRegistrationResponse::new(
+        ClientId::new(client.id.to_string()),
+        redirect_uris,
+        ExtraClientMetadata {
+            require_pkce: client.require_pkce,
+            scope: client.scope.clone(),
+            access_token_format: client.access_token_format,
+            require_pushed_authorization_requests: client.require_par,
+            post_logout_redirect_uris: if client.post_logout_redirect_uris.is_empty() {
+                None
+            } else {
+                Some(client.post_logout_redirect_uris.clone())
+            },
+            software_statement: client.software_statement.clone(),
+        },
+        EmptyAdditionalClientRegistrationResponse {},
+    )
+    .set_client_secret(client_secret.map(|x| ClientSecret::new(x.to_string())))
+    .set_client_secret_expires_at(client.client_secret_hash.as_ref().map(|_| {
+        // RFC 7591, section 3.2.1: 0 means the secret doesn't expire.
+        let expires = client
+            .client_secret_expires_at
+            .map_or(0, |x| x.unix_timestamp());
+
+        Utc.timestamp_opt(expires, 0).unwrap()
+    }))
+    .set_registration_access_token(
+        registration_token.map(|x| RegistrationAccessToken::new(x.to_string())),
+    )
+    .set_registration_client_uri(Some(links.oidc_config_client(client.id)))
+    .set_client_name(Some(LocalizedClaim::from(ClientName::new(
+        client.client_name.clone(),
+    ))))
+    .set_logo_uri(Some(LocalizedClaim::from(LogoUrl::from_url(
+        client.logo_uri.clone(),
+    ))))
+    .set_client_uri(
+        client
+            .client_uri
+            .clone()
+            .map(|x| LocalizedClaim::from(ClientUrl::from_url(x))),
+    )
+    .set_policy_uri(
+        client
+            .policy_uri
+            .clone()
+            .map(|x| LocalizedClaim::from(PolicyUrl::from_url(x))),
+    )
+    .set_tos_uri(
+        client
+            .tos_uri
+            .clone()
+            .map(|x| LocalizedClaim::from(ToSUrl::from_url(x))),
+    )
+    .set_application_type(Some(client.app_type.clone()))
+    .set_token_endpoint_auth_method(Some(client.token_endpoint_auth_method.clone()))
+    .set_grant_types(Some(client.grant_types.clone()))
+    .set_response_types(Some(client.response_types.clone()))
+    .set_contacts(if contacts.is_empty() {
+        None
+    } else {
+        Some(contacts)
+    })
+    .set_jwks(client.jwks.clone())
+    .set_jwks_uri(
+        client
+            .jwks_uri
+            .clone()
+            .map(openidconnect::JsonWebKeySetUrl::from_url),
+    )
+    .set_default_max_age(
+        client
+            .default_max_age
+            .and_then(|x| u64::try_from(x.whole_seconds()).ok())
+            .map(std::time::Duration::from_secs),
+    )
+    .set_require_auth_time(Some(client.require_auth_time))
+    .set_request_uris(if client.request_uris.is_empty() {
+        None
+    } else {
+        Some(client.request_uris.clone())
+    }))
+}
+
+// RFC 7592, section 2: unknown clients and bad tokens look the same.
+async fn authenticate(
+    client_id: &str,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    state: &ServerState,
+) -> Result<Client, ApiError> {
+    let (Ok(client_id), Some(auth)) = (EntityId::try_from(client_id), auth) else {
+        return Err(ApiError::FromAxum(Box::new(invalid_token())));
+    };
+
+    Client::get(client_id, &state.pool)
+        .await?
+        .filter(|x| x.verify_registration_token(auth.token(), &state.secret_hasher))
+        .ok_or_else(|| ApiError::FromAxum(Box::new(invalid_token())))
+}
+
+fn invalid_metadata(description: &str) -> ApiError {
+    StandardErrorResponse::new(
+        CoreRegisterErrorResponseType::InvalidClientMetadata,
+        Some(description.to_string()),
+        None,
+    )
+    .into()
+}
+
+pub async fn get_client(
+    state: ServerState,
+    Path(client_id): Path<String>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let client = authenticate(&client_id, auth, &state).await?;
+
+    let mut conn = state.pool.acquire().await?;
+
+    Ok(Json(
+        registration_response(&client, None, None, &mut conn, &state.links).await?,
+    ))
+}
+
+pub async fn update_client(
+    state: ServerState,
+    Path(client_id): Path<String>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    Json(mut req): Json<Value>,
+) -> Result<impl IntoResponse, ApiError> {
+    let client = authenticate(&client_id, auth, &state).await?;
+
+    let Some(req_obj) = req.as_object_mut() else {
+        return Err(invalid_metadata("request body must be an object"));
+    };
+
+    // RFC 7592, section 2.2
+    if req_obj.get("client_id").and_then(|x| x.as_str()) != Some(&client_id) {
+        return Err(invalid_metadata("client_id doesn't match this client"));
+    }
+
+    if let Some(secret) = req_obj.get("client_secret") {
+        let valid = match secret.as_str() {
+            Some(secret) => {
+                client
+                    .verify_secret(secret, &state.secret_hasher, &state.pool)
+                    .await?
+            }
+            None => false,
+        };
+
+        if !valid {
+            return Err(invalid_metadata("client_secret doesn't match this client"));
+        }
+    }
+
+    // RFC 7591, section 2.3: the statement stays with the client, so its claims keep taking
+    // precedence over the ones in the request.
+    if let Some(statement) = &client.software_statement {
+        match req_obj.get("software_statement") {
+            None => {
+                req_obj.insert(
+                    "software_statement".to_string(),
+                    Value::String(statement.clone()),
+                );
+            }
+            Some(x) if x.as_str() == Some(statement) => {}
+            Some(_) => {
+                return Err(invalid_metadata(
+                    "software_statement can't be changed or removed",
+                ))
+            }
+        }
+    }
+
+    state
+        .registration_policy
+        .apply_software_statement(req_obj)?;
+
+    for field in [
+        "client_id",
+        "client_secret",
+        "registration_access_token",
+        "registration_client_uri",
+        "client_id_issued_at",
+        "client_secret_expires_at",
+    ] {
+        req_obj.remove(field);
+    }
+
+    let req: ClientMetadata =
+        serde_json::from_value(req).map_err(|x| invalid_metadata(&x.to_string()))?;
+
+    let fields = validate_metadata(&req, &state.links)?;
+
+    // Switching methods would need new credentials, and those are only issued at registration.
+    if fields.token_endpoint_auth_method != client.token_endpoint_auth_method {
+        return Err(invalid_metadata(
+            "token_endpoint_auth_method can't be changed",
+        ));
+    }
+
+    let mut conn = state.pool.acquire().await?;
+
+    let id = client.id;
+
+    let res = conn.transaction::<_, _, ApiError>(|tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>| {
+        Box::pin(async move {
+            state
+                .registration_policy
+                .check(&req, &fields.app_type, Some(client.id), tx)
+                .await?;
+
+            // RFC 7592, section 2.2: the request replaces the client's metadata, so omitted
+            // fields are cleared.
+            let app_type_q = fields.app_type.as_ref();
+            let grant_types_q = SqlJson(&fields.grant_types);
+            let scope_q = req.additional_metadata().scope.as_ref().map(|x| x.to_string());
+            let access_token_format_q = req.additional_metadata().access_token_format;
+            let require_par_q = req.additional_metadata().require_pushed_authorization_requests;
+            let jwks_q = req.jwks().map(SqlJson);
+            let jwks_uri_q = req.jwks_uri().map(|x| x.as_str());
+            let request_uris_q = SqlJson(req.request_uris().cloned().unwrap_or_default());
+            let response_types_q = SqlJson(&fields.response_types);
+            let post_logout_redirect_uris_q = SqlJson(
+                req.additional_metadata()
+                    .post_logout_redirect_uris
+                    .clone()
+                    .unwrap_or_default(),
+            );
+            let require_auth_time_q = req.require_auth_time().unwrap_or(false);
+            let software_statement_q = req.additional_metadata().software_statement.as_deref();
+
+            sqlx::query!(
+                "
+                UPDATE clients
+                SET client_name = $1, app_type = $2, client_uri = $3, logo_uri = $4, require_pkce = $5, grant_types = $6, scope = $7, access_token_format = $8, require_par = $9, jwks = $10, jwks_uri = $11, request_uris = $12, response_types = $13, post_logout_redirect_uris = $14, policy_uri = $15, tos_uri = $16, default_max_age = $17, require_auth_time = $18, software_statement = $19
+                WHERE id = $20
+                ",
+                fields.client_name,
+                app_type_q,
+                fields.client_uri,
+                fields.logo_uri,
+                fields.require_pkce,
+                grant_types_q,
+                scope_q,
+                access_token_format_q,
+                require_par_q,
+                jwks_q,
+                jwks_uri_q,
+                request_uris_q,
+                response_types_q,
+                post_logout_redirect_uris_q,
+                fields.policy_uri,
+                fields.tos_uri,
+                fields.default_max_age,
+                require_auth_time_q,
+                software_statement_q,
+                client.id
+            )
+            .execute(&mut **tx)
+            .await?;
+
+            sqlx::query!(
+                "
+                DELETE FROM client_redirect_uris
+                WHERE client_id = $1
+                ",
+                client.id
+            )
+            .execute(&mut **tx)
+            .await?;
+
+            for redirect_uri in req.redirect_uris() {
+                let uri_q = redirect_uri.as_str();
+
+                sqlx::query!(
+                    "
+                    INSERT INTO client_redirect_uris
+                    (client_id, redirect_uri)
+                    VALUES
+                    ($1, $2)
+                    ",
+                    client.id,
+                    uri_q
+                )
+                .execute(&mut **tx)
+                .await?;
+            }
+
+            sqlx::query!(
+                "
+                DELETE FROM client_contacts
+                WHERE client_id = $1
+                ",
+                client.id
+            )
+            .execute(&mut **tx)
+            .await?;
+
+            for contact in req.contacts().iter().flat_map(|x| x.iter()) {
+                let email_q = contact.as_str();
+
+                sqlx::query!(
+                    "
+                    INSERT INTO client_contacts
+                    (client_id, email)
+                    VALUES
+                    ($1, $2)
+                    ",
+                    client.id,
+                    email_q
+                )
+                .execute(&mut **tx)
+                .await?;
+            }
+
+            let Some(client) = Client::get(client.id, &mut **tx).await? else {
+                return Err(ApiError::FromAxum(Box::new(invalid_token())));
+            };
+
+            Ok(Json(
+                registration_response(&client, None, None, tx, &state.links).await?,
+            ))
+        })
+    })
+    .await;
+
+    Client::forget_keys(id);
+
+    res
+}
+
+pub async fn delete_client(
+    state: ServerState,
+    Path(client_id): Path<String>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let client = authenticate(&client_id, auth, &state).await?;
+
+    Client::delete(client.id, &state.pool).await?;
+
+    tracing::info!("Client {} deleted itself", client.id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct RotateSecretRequest {
+    grace_period: Option<i64>,
+    client_secret_expires_at: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ClientSecretInfo {
+    current: bool,
+    expires_at: i64,
+}
+
+#[derive(Serialize)]
+pub struct ClientSecretsResponse {
+    client_secrets: Vec<ClientSecretInfo>,
+}
+
+// Secrets themselves are never returned, only when they stop working.
+pub async fn list_secrets(
+    state: ServerState,
+    Path(client_id): Path<String>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let client = authenticate(&client_id, auth, &state).await?;
+
+    let mut client_secrets = Vec::new();
+
+    if client.client_secret_hash.is_some() && !client.secret_expired() {
+        client_secrets.push(ClientSecretInfo {
+            current: true,
+            expires_at: client
+                .client_secret_expires_at
+                .map_or(0, |x| x.unix_timestamp()),
+        });
+    }
+
+    for secret in RetiredClientSecret::list(client.id, &state.pool).await? {
+        client_secrets.push(ClientSecretInfo {
+            current: false,
+            expires_at: secret.expires.unix_timestamp(),
+        });
+    }
+
+    Ok(Json(ClientSecretsResponse { client_secrets }))
+}
+
+pub async fn rotate_secret(
+    state: ServerState,
+    Path(client_id): Path<String>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    Json(req): Json<RotateSecretRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let client = authenticate(&client_id, auth, &state).await?;
+
+    if client.client_secret_hash.is_none() {
+        return Err(invalid_metadata(
+            "client doesn't authenticate with a secret",
+        ));
+    }
+
+    let grace_period = req
+        .grace_period
+        .map_or(RetiredClientSecret::DEFAULT_GRACE_PERIOD, Duration::seconds);
+
+    if grace_period.is_negative() || grace_period > RetiredClientSecret::MAX_GRACE_PERIOD {
+        return Err(invalid_metadata("grace_period is out of range"));
+    }
+
+    let expires_at = match req.client_secret_expires_at {
+        None | Some(0) => None,
+        Some(x) => match OffsetDateTime::from_unix_timestamp(x) {
+            Ok(x) if x > OffsetDateTime::now_utc() => Some(x),
+            _ => {
+                return Err(invalid_metadata(
+                    "client_secret_expires_at must be in the future",
+                ))
+            }
+        },
+    };
+
+    let client_secret = client
+        .rotate_secret(grace_period, expires_at, &state.secret_hasher, &state.pool)
+        .await?;
+
+    tracing::info!("Client {} rotated its secret", client.id);
+
+    let mut conn = state.pool.acquire().await?;
+
+    let Some(client) = Client::get(client.id, &mut *conn).await? else {
+        return Err(ApiError::FromAxum(Box::new(invalid_token())));
+    };
+
+    Ok(Json(
+        registration_response(&client, Some(&client_secret), None, &mut conn, &state.links).await?,
+    ))
+}
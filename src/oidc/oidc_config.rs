@@ -4,34 +4,176 @@ use axum::{extract::State, response::IntoResponse, Json};
 
 use openidconnect::{
     core::{
-        CoreClaimName, CoreJwsSigningAlgorithm, CoreProviderMetadata, CoreResponseType,
+        CoreAuthDisplay, CoreClaimName, CoreClaimType, CoreClientAuthMethod, CoreGrantType,
+        CoreJsonWebKey, CoreJsonWebKeyType, CoreJsonWebKeyUse, CoreJweContentEncryptionAlgorithm,
+        CoreJweKeyManagementAlgorithm, CoreJwsSigningAlgorithm, CoreResponseMode, CoreResponseType,
         CoreSubjectIdentifierType,
     },
-    AuthUrl, EmptyAdditionalProviderMetadata, IssuerUrl, JsonWebKeySet, JsonWebKeySetUrl,
+    AdditionalProviderMetadata, AuthUrl, IssuerUrl, JsonWebKeySet, JsonWebKeySetUrl,
     RegistrationUrl, ResponseTypes, Scope, TokenUrl, UserInfoUrl,
 };
+use serde::{Deserialize, Serialize};
+use url::Url;
 
 use crate::{
     error::ApiError, links::ServerLinks, model::signing_keys::SigningKey, state::ServerState,
+    util::pkce::CodeChallengeMethod,
 };
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExtraProviderMetadata {
+    pub code_challenge_methods_supported: Vec<CodeChallengeMethod>,
+    pub revocation_endpoint: Url,
+    pub revocation_endpoint_auth_methods_supported: Vec<CoreClientAuthMethod>,
+    pub introspection_endpoint: Url,
+    pub introspection_endpoint_auth_methods_supported: Vec<CoreClientAuthMethod>,
+    pub device_authorization_endpoint: Url,
+    pub pushed_authorization_request_endpoint: Url,
+    pub require_pushed_authorization_requests: bool,
+}
+
+impl AdditionalProviderMetadata for ExtraProviderMetadata {}
+
+type ProviderMetadata = openidconnect::ProviderMetadata<
+    ExtraProviderMetadata,
+    CoreAuthDisplay,
+    CoreClientAuthMethod,
+    CoreClaimName,
+    CoreClaimType,
+    CoreGrantType,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJweKeyManagementAlgorithm,
+    CoreJwsSigningAlgorithm,
+    CoreJsonWebKeyType,
+    CoreJsonWebKeyUse,
+    CoreJsonWebKey,
+    CoreResponseMode,
+    CoreResponseType,
+    CoreSubjectIdentifierType,
+>;
+
+pub fn response_types_supported() -> Vec<ResponseTypes<CoreResponseType>> {
+    vec![
+        ResponseTypes::new(vec![CoreResponseType::Code]),
+        ResponseTypes::new(vec![CoreResponseType::IdToken]),
+        ResponseTypes::new(vec![CoreResponseType::Code, CoreResponseType::IdToken]),
+        ResponseTypes::new(vec![
+            CoreResponseType::Code,
+            CoreResponseType::IdToken,
+            CoreResponseType::Token,
+        ]),
+    ]
+}
+
+// Response types are unordered, so "id_token code" is the same as "code id_token".
+pub fn response_types_contain(
+    list: &[ResponseTypes<CoreResponseType>],
+    response_type: &ResponseTypes<CoreResponseType>,
+) -> bool {
+    fn normalize(x: &ResponseTypes<CoreResponseType>) -> Vec<&str> {
+        let mut x: Vec<&str> = x.iter().map(|x| x.as_ref()).collect();
+        x.sort_unstable();
+        x.dedup();
+        x
+    }
+
+    let response_type = normalize(response_type);
+
+    list.iter().any(|x| normalize(x) == response_type)
+}
+
+pub fn client_auth_methods_supported() -> Vec<CoreClientAuthMethod> {
+    vec![
+        CoreClientAuthMethod::ClientSecretBasic,
+        CoreClientAuthMethod::ClientSecretPost,
+        CoreClientAuthMethod::ClientSecretJwt,
+        CoreClientAuthMethod::PrivateKeyJwt,
+        CoreClientAuthMethod::None,
+    ]
+}
+
+fn client_assertion_algs_supported() -> Vec<CoreJwsSigningAlgorithm> {
+    vec![
+        CoreJwsSigningAlgorithm::HmacSha256,
+        CoreJwsSigningAlgorithm::HmacSha384,
+        CoreJwsSigningAlgorithm::HmacSha512,
+        CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+        CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha384,
+        CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha512,
+        CoreJwsSigningAlgorithm::RsaSsaPssSha256,
+        CoreJwsSigningAlgorithm::RsaSsaPssSha384,
+        CoreJwsSigningAlgorithm::RsaSsaPssSha512,
+        CoreJwsSigningAlgorithm::EcdsaP256Sha256,
+        CoreJwsSigningAlgorithm::EcdsaP384Sha384,
+    ]
+}
+
+// Request objects are only verified with the client's public keys.
+pub fn request_object_algs_supported() -> Vec<CoreJwsSigningAlgorithm> {
+    vec![
+        CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+        CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha384,
+        CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha512,
+        CoreJwsSigningAlgorithm::RsaSsaPssSha256,
+        CoreJwsSigningAlgorithm::RsaSsaPssSha384,
+        CoreJwsSigningAlgorithm::RsaSsaPssSha512,
+        CoreJwsSigningAlgorithm::EcdsaP256Sha256,
+        CoreJwsSigningAlgorithm::EcdsaP384Sha384,
+    ]
+}
+
 pub async fn configuration(links: State<Arc<ServerLinks>>) -> impl IntoResponse {
-    let metadata = CoreProviderMetadata::new(
+    let metadata = ProviderMetadata::new(
         IssuerUrl::from_url(links.issuer.clone()),
         AuthUrl::from_url(links.oauth_authorize.clone()),
         JsonWebKeySetUrl::from_url(links.oidc_jwks.clone()),
-        vec![ResponseTypes::new(vec![CoreResponseType::Code])],
+        response_types_supported(),
         vec![CoreSubjectIdentifierType::Public],
         vec![CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256],
-        EmptyAdditionalProviderMetadata {},
+        ExtraProviderMetadata {
+            code_challenge_methods_supported: vec![
+                CodeChallengeMethod::S256,
+                CodeChallengeMethod::Plain,
+            ],
+            revocation_endpoint: links.oauth_revoke.clone(),
+            revocation_endpoint_auth_methods_supported: client_auth_methods_supported(),
+            introspection_endpoint: links.oauth_introspect.clone(),
+            introspection_endpoint_auth_methods_supported: client_auth_methods_supported(),
+            device_authorization_endpoint: links.oauth_device_authorization.clone(),
+            pushed_authorization_request_endpoint: links.oauth_par.clone(),
+            require_pushed_authorization_requests: false,
+        },
     )
     .set_token_endpoint(Some(TokenUrl::from_url(links.oauth_token.clone())))
     .set_userinfo_endpoint(Some(UserInfoUrl::from_url(links.oidc_userinfo.clone())))
     .set_registration_endpoint(Some(RegistrationUrl::from_url(links.oidc_register.clone())))
+    .set_token_endpoint_auth_methods_supported(Some(client_auth_methods_supported()))
+    .set_token_endpoint_auth_signing_alg_values_supported(Some(client_assertion_algs_supported()))
     .set_scopes_supported(Some(vec![
         Scope::new("openid".to_string()),
         Scope::new("profile".to_string()),
         Scope::new("email".to_string()),
+        Scope::new("offline_access".to_string()),
+    ]))
+    .set_response_modes_supported(Some(vec![
+        CoreResponseMode::Query,
+        CoreResponseMode::Fragment,
+        CoreResponseMode::FormPost,
+        CoreResponseMode::Extension("jwt".to_string()),
+        CoreResponseMode::Extension("query.jwt".to_string()),
+        CoreResponseMode::Extension("fragment.jwt".to_string()),
+        CoreResponseMode::Extension("form_post.jwt".to_string()),
+    ]))
+    .set_request_parameter_supported(Some(true))
+    .set_request_uri_parameter_supported(Some(true))
+    .set_require_request_uri_registration(Some(true))
+    .set_request_object_signing_alg_values_supported(Some(request_object_algs_supported()))
+    .set_grant_types_supported(Some(vec![
+        CoreGrantType::AuthorizationCode,
+        CoreGrantType::RefreshToken,
+        CoreGrantType::Implicit,
+        CoreGrantType::ClientCredentials,
+        CoreGrantType::DeviceCode,
     ]))
     .set_claims_supported(Some(vec![
         CoreClaimName::new("sub".to_string()),
@@ -1,70 +1,402 @@
+use axum::headers::{authorization::Bearer, Authorization};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use axum::Json;
+use axum::{Json, TypedHeader};
 use openidconnect::core::{
-    CoreClientMetadata, CoreClientRegistrationResponse, CoreRegisterErrorResponseType,
+    CoreApplicationType, CoreClientAuthMethod, CoreGrantType, CoreRegisterErrorResponseType,
+    CoreResponseType,
 };
-use openidconnect::registration::{
-    EmptyAdditionalClientMetadata, EmptyAdditionalClientRegistrationResponse,
-};
-use openidconnect::{ClientId, ClientSecret, RegistrationAccessToken, StandardErrorResponse};
+use openidconnect::{ResponseTypes, StandardErrorResponse};
+use serde_json::Value;
+use sqlx::types::Json as SqlJson;
 use sqlx::Connection;
 
 use crate::error::ApiError;
+use crate::links::ServerLinks;
+use crate::model::clients::Client;
+use crate::oidc::client_metadata::ClientMetadata;
+use crate::oidc::oidc_config::{
+    client_auth_methods_supported, response_types_contain, response_types_supported,
+};
 use crate::state::ServerState;
 use crate::util::id::EntityId;
+use crate::util::redirect_uri;
+
+use super::oidc_client_config::registration_response;
+
+// OIDC Dynamic Client Registration, section 2 and RFC 8252, section 7
+fn validate_redirect_uris(
+    req: &ClientMetadata,
+    app_type: &CoreApplicationType,
+) -> Result<(), ApiError> {
+    if *app_type != CoreApplicationType::Native {
+        return Ok(());
+    }
+
+    for redirect_uri in req.redirect_uris() {
+        let redirect_uri = redirect_uri.url();
+
+        let valid = match redirect_uri.scheme() {
+            "https" => true,
+            "http" => redirect_uri::is_loopback(redirect_uri),
+            _ => redirect_uri::is_private_use_scheme(redirect_uri),
+        };
+
+        if !valid {
+            return Err(StandardErrorResponse::new(
+                CoreRegisterErrorResponseType::InvalidRedirectUri,
+                Some(format!(
+                    "{redirect_uri} must be a loopback, https or private-use URI"
+                )),
+                None,
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+// Metadata from a registration or update request, validated and with defaults filled in.
+pub struct ClientFields {
+    pub client_name: String,
+    pub app_type: CoreApplicationType,
+    pub client_uri: Option<String>,
+    pub logo_uri: String,
+    pub policy_uri: Option<String>,
+    pub tos_uri: Option<String>,
+    pub response_types: Vec<ResponseTypes<CoreResponseType>>,
+    pub grant_types: Vec<CoreGrantType>,
+    pub token_endpoint_auth_method: CoreClientAuthMethod,
+    pub default_max_age: Option<i64>,
+    pub require_pkce: bool,
+}
+
+pub fn validate_metadata(
+    req: &ClientMetadata,
+    links: &ServerLinks,
+) -> Result<ClientFields, ApiError> {
+    let Some(client_name) = req
+        .client_name()
+        .and_then(|x| x.get(None))
+        .map(|x| (**x).clone())
+    else {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some("client_name is required".to_string()),
+            None,
+        )
+        .into());
+    };
+
+    let app_type = req
+        .application_type()
+        .cloned()
+        .unwrap_or(CoreApplicationType::Web);
+
+    let client_uri = req
+        .client_uri()
+        .and_then(|x| x.get(None))
+        .map(|x| x.url().to_string());
+
+    let logo_uri = req
+        .logo_uri()
+        .and_then(|x| x.get(None))
+        .map(|x| x.url().to_string())
+        .unwrap_or_else(|| format!("{}/static/default_icon.png", links.issuer));
+
+    let response_types = req
+        .response_types()
+        .cloned()
+        .unwrap_or_else(|| vec![ResponseTypes::new(vec![CoreResponseType::Code])]);
+
+    let supported_response_types = response_types_supported();
+
+    if let Some(response_type) = response_types
+        .iter()
+        .find(|x| !response_types_contain(&supported_response_types, x))
+    {
+        let response_type: Vec<&str> = response_type.iter().map(|x| x.as_ref()).collect();
+
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some(format!(
+                "response type {} is not supported",
+                response_type.join(" ")
+            )),
+            None,
+        )
+        .into());
+    }
+
+    let uses_code = response_types
+        .iter()
+        .any(|x| x.contains(&CoreResponseType::Code));
+    let uses_implicit = response_types
+        .iter()
+        .any(|x| x.iter().any(|x| *x != CoreResponseType::Code));
+
+    let grant_types = req.grant_types().cloned().unwrap_or_else(|| {
+        let mut grant_types = vec![
+            CoreGrantType::AuthorizationCode,
+            CoreGrantType::RefreshToken,
+        ];
+
+        if uses_implicit {
+            grant_types.push(CoreGrantType::Implicit);
+        }
+
+        grant_types
+    });
+
+    if let Some(grant_type) = grant_types.iter().find(|x| {
+        !matches!(
+            x,
+            CoreGrantType::AuthorizationCode
+                | CoreGrantType::RefreshToken
+                | CoreGrantType::Implicit
+                | CoreGrantType::ClientCredentials
+                | CoreGrantType::DeviceCode
+        )
+    }) {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some(format!(
+                "grant type {} is not supported",
+                grant_type.as_ref()
+            )),
+            None,
+        )
+        .into());
+    }
+
+    // OIDC Dynamic Client Registration, section 2
+    if (uses_code && !grant_types.contains(&CoreGrantType::AuthorizationCode))
+        || (uses_implicit && !grant_types.contains(&CoreGrantType::Implicit))
+    {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some("grant_types don't match response_types".to_string()),
+            None,
+        )
+        .into());
+    }
+
+    // The authorize endpoint has nowhere to send the response without a redirect URI.
+    if req.redirect_uris().is_empty()
+        && (grant_types.contains(&CoreGrantType::AuthorizationCode)
+            || grant_types.contains(&CoreGrantType::Implicit))
+    {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidRedirectUri,
+            Some("redirect_uris can't be empty for this client's grant types".to_string()),
+            None,
+        )
+        .into());
+    }
+
+    let token_endpoint_auth_method = req
+        .token_endpoint_auth_method()
+        .cloned()
+        .unwrap_or(CoreClientAuthMethod::ClientSecretBasic);
+
+    if !client_auth_methods_supported().contains(&token_endpoint_auth_method) {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some(format!(
+                "token endpoint auth method {} is not supported",
+                token_endpoint_auth_method.as_ref()
+            )),
+            None,
+        )
+        .into());
+    }
+
+    let policy_uri = req
+        .policy_uri()
+        .and_then(|x| x.get(None))
+        .map(|x| x.url().to_string());
+
+    let tos_uri = req
+        .tos_uri()
+        .and_then(|x| x.get(None))
+        .map(|x| x.url().to_string());
+
+    if req.jwks().is_some() && req.jwks_uri().is_some() {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some("jwks and jwks_uri can't be used together".to_string()),
+            None,
+        )
+        .into());
+    }
+
+    // The server fetches these itself, so they can't point into the network it runs in.
+    if let Some(uri) = req
+        .jwks_uri()
+        .map(|x| x.url())
+        .into_iter()
+        .chain(req.request_uris().iter().flat_map(|x| x.iter().map(|x| x.url())))
+        .find(|x| x.scheme() != "https" || redirect_uri::is_internal(x))
+    {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some(format!("{uri} must be a public https URI")),
+            None,
+        )
+        .into());
+    }
+
+    if token_endpoint_auth_method == CoreClientAuthMethod::PrivateKeyJwt
+        && req.jwks().is_none()
+        && req.jwks_uri().is_none()
+    {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some("private_key_jwt requires jwks or jwks_uri".to_string()),
+            None,
+        )
+        .into());
+    }
+
+    // Client credentials only make sense for clients that can authenticate.
+    if token_endpoint_auth_method == CoreClientAuthMethod::None
+        && grant_types.contains(&CoreGrantType::ClientCredentials)
+    {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some("public clients can't use the client_credentials grant".to_string()),
+            None,
+        )
+        .into());
+    }
+
+    let Ok(default_max_age) = req
+        .default_max_age()
+        .map(|x| i64::try_from(x.as_secs()))
+        .transpose()
+    else {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some("default_max_age is out of range".to_string()),
+            None,
+        )
+        .into());
+    };
+
+    validate_redirect_uris(req, &app_type)?;
+
+    // OAuth 2.0 Security BCP, section 2.1.1: public clients have to use PKCE.
+    let require_pkce = req.additional_metadata().require_pkce
+        || token_endpoint_auth_method == CoreClientAuthMethod::None;
+
+    Ok(ClientFields {
+        client_name,
+        app_type,
+        client_uri,
+        logo_uri,
+        policy_uri,
+        tos_uri,
+        response_types,
+        grant_types,
+        token_endpoint_auth_method,
+        default_max_age,
+        require_pkce,
+    })
+}
 
 pub async fn register_client(
     state: ServerState,
-    Json(req): Json<CoreClientMetadata>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+    Json(mut req): Json<Value>,
 ) -> Result<impl IntoResponse, ApiError> {
+    state.registration_policy.authorize(auth)?;
+
+    let Some(req_obj) = req.as_object_mut() else {
+        return Err(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some("request body must be an object".to_string()),
+            None,
+        )
+        .into());
+    };
+
+    state
+        .registration_policy
+        .apply_software_statement(req_obj)?;
+
+    let req: ClientMetadata = serde_json::from_value(req).map_err(|x| {
+        ApiError::from(StandardErrorResponse::new(
+            CoreRegisterErrorResponseType::InvalidClientMetadata,
+            Some(x.to_string()),
+            None,
+        ))
+    })?;
+
     let mut conn = state.pool.acquire().await?;
 
     conn.transaction::<_, _, ApiError>(|tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>| {
         Box::pin(async move {
-            let Some(client_name) = req
-                .client_name()
-                .and_then(|x| x.get(None))
-                .map(|x| (**x).clone())
-            else {
-                return Err(StandardErrorResponse::new(
-                    CoreRegisterErrorResponseType::InvalidClientMetadata,
-                    Some("client_name is required for registration".to_string()),
-                    None,
-                )
-                .into());
-            };
-
-            let app_type = req
-                .application_type()
-                .cloned()
-                .unwrap_or(openidconnect::core::CoreApplicationType::Web);
-
-            let client_uri = req
-                .client_uri()
-                .and_then(|x| x.get(None))
-                .map(|x| x.url().to_string());
+            let ClientFields {
+                client_name,
+                app_type,
+                client_uri,
+                logo_uri,
+                policy_uri,
+                tos_uri,
+                response_types,
+                grant_types,
+                token_endpoint_auth_method,
+                default_max_age,
+                require_pkce,
+            } = validate_metadata(&req, &state.links)?;
 
-            let logo_uri = req
-                .logo_uri()
-                .and_then(|x| x.get(None))
-                .map(|x| x.url().to_string())
-                .unwrap_or_else(|| format!("{}/static/default_icon.png", state.links.issuer));
+            state
+                .registration_policy
+                .check(&req, &app_type, None, tx)
+                .await?;
 
             let client_id = EntityId::generate(&mut rand::thread_rng());
             let registration_token = crate::util::gen_secret();
-            let client_secret = crate::util::gen_secret();
+            let client_secret = match token_endpoint_auth_method {
+                CoreClientAuthMethod::ClientSecretBasic
+                | CoreClientAuthMethod::ClientSecretPost
+                | CoreClientAuthMethod::ClientSecretJwt => Some(crate::util::gen_secret()),
+                _ => None,
+            };
 
             let app_type_q = app_type.as_ref();
-            let client_secret_q = &client_secret;
-            let reg_token_q = &registration_token;
+            let client_secret_q = client_secret
+                .as_deref()
+                .map(|x| state.secret_hasher.hash(x));
+            let client_secret_encrypted_q = client_secret.as_deref().and_then(|x| {
+                Client::encrypt_secret(client_id, &token_endpoint_auth_method, x, &state.secret_hasher)
+            });
+            let reg_token_q = state.secret_hasher.hash(&registration_token);
+            let grant_types_q = SqlJson(&grant_types);
+            let scope_q = req.additional_metadata().scope.as_ref().map(|x| x.to_string());
+            let access_token_format_q = req.additional_metadata().access_token_format;
+            let require_par_q = req.additional_metadata().require_pushed_authorization_requests;
+            let jwks_q = req.jwks().map(SqlJson);
+            let jwks_uri_q = req.jwks_uri().map(|x| x.as_str());
+            let request_uris_q = SqlJson(req.request_uris().cloned().unwrap_or_default());
+            let response_types_q = SqlJson(&response_types);
+            let token_endpoint_auth_method_q = token_endpoint_auth_method.as_ref();
+            let post_logout_redirect_uris_q = SqlJson(
+                req.additional_metadata()
+                    .post_logout_redirect_uris
+                    .clone()
+                    .unwrap_or_default(),
+            );
+            let require_auth_time_q = req.require_auth_time().unwrap_or(false);
+            let software_statement_q = req.additional_metadata().software_statement.as_deref();
 
             sqlx::query!(
                 "
                 INSERT INTO clients
-                (id, client_name, app_type, client_uri, logo_uri, registration_token, client_secret)
+                (id, client_name, app_type, client_uri, logo_uri, registration_token_hash, client_secret_hash, client_secret_encrypted, secrets_hashed, require_pkce, grant_types, scope, access_token_format, require_par, jwks, jwks_uri, request_uris, response_types, token_endpoint_auth_method, post_logout_redirect_uris, policy_uri, tos_uri, default_max_age, require_auth_time, software_statement)
                 VALUES
-                ($1, $2, $3, $4, $5, $6, $7)
+                ($1, $2, $3, $4, $5, $6, $7, $8, TRUE, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24)
                 ",
                 client_id,
                 client_name,
@@ -72,7 +404,24 @@ pub async fn register_client(
                 client_uri,
                 logo_uri,
                 reg_token_q,
-                client_secret_q
+                client_secret_q,
+                client_secret_encrypted_q,
+                require_pkce,
+                grant_types_q,
+                scope_q,
+                access_token_format_q,
+                require_par_q,
+                jwks_q,
+                jwks_uri_q,
+                request_uris_q,
+                response_types_q,
+                token_endpoint_auth_method_q,
+                post_logout_redirect_uris_q,
+                policy_uri,
+                tos_uri,
+                default_max_age,
+                require_auth_time_q,
+                software_statement_q
             )
             .execute(&mut **tx)
             .await?;
@@ -111,25 +460,17 @@ pub async fn register_client(
                 .await?;
             }
 
+            let client = Client::get(client_id, &mut **tx).await?.unwrap();
+
             Ok((
                 StatusCode::CREATED,
-                Json(
-                    CoreClientRegistrationResponse::new(
-                        ClientId::new(client_id.to_string()),
-                        req.redirect_uris().clone(),
-                        EmptyAdditionalClientMetadata {},
-                        EmptyAdditionalClientRegistrationResponse {},
-                    )
-                    .set_client_secret(Some(ClientSecret::new(client_secret.clone())))
-                    .set_client_secret_expires_at(None)
-                    .set_registration_access_token(Some(RegistrationAccessToken::new(
-                        registration_token.clone(),
-                    )))
-                    .set_registration_client_uri(Some(state.links.oidc_config_client(client_id)))
-                    .set_application_type(Some(app_type))
-                    .set_redirect_uris(req.redirect_uris().clone())
-                    .set_contacts(req.contacts().cloned()),
-                ),
+                Json(registration_response(
+                    &client,
+                    client_secret.as_deref(),
+                    Some(&registration_token),
+                    tx,
+                    &state.links
+                ).await?),
             ))
         })
     })
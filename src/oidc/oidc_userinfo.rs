@@ -4,7 +4,11 @@ use openidconnect::{
     core::CoreUserInfoClaims, EmptyAdditionalClaims,
 };
 
-use crate::{error::ApiError, model::access_tokens::AccessToken, state::ServerState};
+use crate::{
+    error::ApiError,
+    model::access_tokens::{self, AccessToken},
+    state::ServerState,
+};
 
 use super::claim_gatherer;
 
@@ -12,8 +16,12 @@ pub async fn userinfo(
     token: AccessToken,
     state: ServerState,
 ) -> Result<impl IntoResponse, ApiError> {
+    let Some(user_id) = token.user_id else {
+        return Err(ApiError::FromAxum(Box::new(access_tokens::invalid_token())));
+    };
+
     Ok(Json(CoreUserInfoClaims::new(
-        claim_gatherer::gather(token.user_id, &token.body.scope, &state.pool).await?,
+        claim_gatherer::gather(user_id, &token.body.scope, &state.pool).await?,
         EmptyAdditionalClaims {},
     )))
 }
@@ -0,0 +1,269 @@
+use anyhow::Context;
+use axum::headers::{authorization::Bearer, Authorization};
+use axum::TypedHeader;
+use openidconnect::core::{
+    CoreApplicationType, CoreJsonWebKey, CoreJsonWebKeySet, CoreRegisterErrorResponseType,
+};
+use openidconnect::StandardErrorResponse;
+use serde_json::{Map, Value};
+use sqlx::SqliteConnection;
+use subtle::{Choice, ConstantTimeEq};
+use time::OffsetDateTime;
+
+use crate::error::ApiError;
+use crate::model::access_tokens::invalid_token;
+use crate::oidc::client_metadata::ClientMetadata;
+use crate::util::id::EntityId;
+use crate::util::{jwt, redirect_uri};
+
+// Initial access tokens are issued by the administrator through REGISTRATION_INITIAL_ACCESS_TOKENS,
+// or as SHA-256 digests through REGISTRATION_INITIAL_ACCESS_TOKEN_HASHES so that the config doesn't
+// hold them in plain text. They can be used any number of times, and stay valid until they're
+// removed from the config.
+#[derive(Debug, Default)]
+pub struct RegistrationPolicy {
+    // RFC 7591, section 3: registration is open when this is empty.
+    pub initial_access_token_hashes: Vec<String>,
+    pub allowed_redirect_hosts: Option<Vec<String>>,
+    pub forbid_http_redirects: bool,
+    pub max_clients_per_contact: Option<i64>,
+    // Scopes clients may register, and so get through the client_credentials grant.
+    pub allowed_scopes: Option<Vec<String>>,
+    // RFC 7591, section 2.3: keys that software statements must be signed with.
+    pub software_statement_keys: Vec<CoreJsonWebKey>,
+}
+
+fn invalid_software_statement(description: &str) -> ApiError {
+    StandardErrorResponse::new(
+        CoreRegisterErrorResponseType::Extension("invalid_software_statement".to_string()),
+        Some(description.to_string()),
+        None,
+    )
+    .into()
+}
+
+fn list_var(name: &str) -> Option<Vec<String>> {
+    dotenvy::var(name).ok().map(|x| {
+        x.split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect()
+    })
+}
+
+fn software_statement_keys() -> anyhow::Result<Vec<CoreJsonWebKey>> {
+    let Ok(path) = dotenvy::var("REGISTRATION_SOFTWARE_STATEMENT_JWKS") else {
+        return Ok(vec![]);
+    };
+
+    let jwks = std::fs::read_to_string(&path)
+        .with_context(|| format!("reading software statement keys from {path}"))?;
+
+    let jwks: CoreJsonWebKeySet = serde_json::from_str(&jwks)
+        .with_context(|| format!("parsing software statement keys from {path}"))?;
+
+    Ok(jwks.keys().clone())
+}
+
+impl RegistrationPolicy {
+    pub fn from_env() -> anyhow::Result<RegistrationPolicy> {
+        Ok(RegistrationPolicy {
+            initial_access_token_hashes: list_var("REGISTRATION_INITIAL_ACCESS_TOKENS")
+                .unwrap_or_default()
+                .iter()
+                .map(|x| crate::util::hash_secret(x))
+                .chain(list_var("REGISTRATION_INITIAL_ACCESS_TOKEN_HASHES").unwrap_or_default())
+                .collect(),
+            allowed_redirect_hosts: list_var("REGISTRATION_ALLOWED_REDIRECT_HOSTS"),
+            forbid_http_redirects: dotenvy::var("REGISTRATION_FORBID_HTTP_REDIRECTS")
+                .ok()
+                .map(|x| x.parse())
+                .transpose()
+                .with_context(|| "parsing REGISTRATION_FORBID_HTTP_REDIRECTS variable")?
+                .unwrap_or(false),
+            max_clients_per_contact: dotenvy::var("REGISTRATION_MAX_CLIENTS_PER_CONTACT")
+                .ok()
+                .map(|x| x.parse())
+                .transpose()
+                .with_context(|| "parsing REGISTRATION_MAX_CLIENTS_PER_CONTACT variable")?,
+            allowed_scopes: list_var("REGISTRATION_ALLOWED_SCOPES"),
+            software_statement_keys: software_statement_keys()?,
+        })
+    }
+
+    pub fn authorize(
+        &self,
+        auth: Option<TypedHeader<Authorization<Bearer>>>,
+    ) -> Result<(), ApiError> {
+        if self.initial_access_token_hashes.is_empty() {
+            return Ok(());
+        }
+
+        let Some(auth) = auth else {
+            return Err(ApiError::FromAxum(Box::new(invalid_token())));
+        };
+
+        let token_hash = crate::util::hash_secret(auth.token());
+
+        // Every hash is checked, so the time taken doesn't depend on which one matched.
+        let valid = self
+            .initial_access_token_hashes
+            .iter()
+            .fold(Choice::from(0), |valid, x| {
+                valid | x.as_bytes().ct_eq(token_hash.as_bytes())
+            });
+
+        if !bool::from(valid) {
+            return Err(ApiError::FromAxum(Box::new(invalid_token())));
+        }
+
+        Ok(())
+    }
+
+    // Claims from the statement take precedence over the ones in the request.
+    pub fn apply_software_statement(&self, req: &mut Map<String, Value>) -> Result<(), ApiError> {
+        let Some(statement) = req.get("software_statement") else {
+            return Ok(());
+        };
+
+        let Some(statement) = statement.as_str() else {
+            return Err(invalid_software_statement(
+                "software_statement must be a string",
+            ));
+        };
+
+        if self.software_statement_keys.is_empty() {
+            return Err(invalid_software_statement(
+                "software statements aren't accepted by this server",
+            ));
+        }
+
+        let (_, claims) =
+            jwt::verify::<Map<String, Value>>(statement, &self.software_statement_keys)
+                .map_err(|x| invalid_software_statement(&x.to_string()))?;
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        if claims
+            .get("exp")
+            .is_some_and(|x| x.as_i64().map(|x| x <= now).unwrap_or(true))
+        {
+            return Err(invalid_software_statement("software statement has expired"));
+        }
+
+        if claims
+            .get("nbf")
+            .is_some_and(|x| x.as_i64().map(|x| x > now).unwrap_or(true))
+        {
+            return Err(invalid_software_statement(
+                "software statement isn't valid yet",
+            ));
+        }
+
+        req.extend(claims.into_iter().filter(|(k, _)| {
+            !matches!(
+                k.as_str(),
+                "iss" | "aud" | "exp" | "nbf" | "iat" | "jti" | "software_statement"
+            )
+        }));
+
+        Ok(())
+    }
+
+    pub fn allows_scope(&self, scope: &str) -> bool {
+        self.allowed_scopes
+            .as_ref()
+            .is_none_or(|x| x.iter().any(|x| x == scope))
+    }
+
+    pub async fn check(
+        &self,
+        req: &ClientMetadata,
+        app_type: &CoreApplicationType,
+        client_id: Option<EntityId>,
+        conn: &mut SqliteConnection,
+    ) -> Result<(), ApiError> {
+        for redirect_uri in req.redirect_uris() {
+            let redirect_uri = redirect_uri.url();
+
+            if self.forbid_http_redirects
+                && *app_type == CoreApplicationType::Web
+                && redirect_uri.scheme() == "http"
+            {
+                return Err(StandardErrorResponse::new(
+                    CoreRegisterErrorResponseType::InvalidRedirectUri,
+                    Some(format!("{redirect_uri} must use https")),
+                    None,
+                )
+                .into());
+            }
+
+            // Native apps redirect to the device itself, so there's no host to allow.
+            let on_device = *app_type == CoreApplicationType::Native
+                && (redirect_uri::is_loopback(redirect_uri)
+                    || redirect_uri::is_private_use_scheme(redirect_uri));
+
+            if let (Some(allowed_hosts), false) = (&self.allowed_redirect_hosts, on_device) {
+                if !redirect_uri
+                    .host_str()
+                    .is_some_and(|host| allowed_hosts.iter().any(|x| x == host))
+                {
+                    return Err(StandardErrorResponse::new(
+                        CoreRegisterErrorResponseType::InvalidRedirectUri,
+                        Some(format!("{redirect_uri} is not on an allowed host")),
+                        None,
+                    )
+                    .into());
+                }
+            }
+        }
+
+        if let Some(scope) = req
+            .additional_metadata()
+            .scope
+            .iter()
+            .flat_map(|x| x.iter())
+            .find(|x| !self.allows_scope(x))
+        {
+            return Err(StandardErrorResponse::new(
+                CoreRegisterErrorResponseType::InvalidClientMetadata,
+                Some(format!("scope {scope} is not allowed")),
+                None,
+            )
+            .into());
+        }
+
+        let Some(max_clients) = self.max_clients_per_contact else {
+            return Ok(());
+        };
+
+        for contact in req.contacts().iter().flat_map(|x| x.iter()) {
+            let email_q = contact.as_str();
+
+            let clients = sqlx::query!(
+                "
+                SELECT COUNT(DISTINCT client_id) AS `count:i64`
+                FROM client_contacts
+                WHERE email = $1 AND client_id IS NOT $2
+                ",
+                email_q,
+                client_id
+            )
+            .fetch_one(&mut *conn)
+            .await?
+            .count;
+
+            if clients >= max_clients {
+                return Err(StandardErrorResponse::new(
+                    CoreRegisterErrorResponseType::InvalidClientMetadata,
+                    Some(format!("too many clients are registered for {email_q}")),
+                    None,
+                )
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
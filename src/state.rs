@@ -1,4 +1,4 @@
-use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
 
 use anyhow::Context;
 use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
@@ -7,13 +7,22 @@ use rsa::pkcs1::EncodeRsaPrivateKey;
 use sqlx::{sqlite::SqlitePoolOptions, Sqlite};
 use url::Url;
 
-use crate::{links::ServerLinks, util::id::EntityId};
+use crate::{
+    auth::session::AuthSession,
+    links::ServerLinks,
+    model::{access_tokens::AccessToken, clients::Client, refresh_tokens::RefreshToken},
+    oidc::registration_policy::RegistrationPolicy,
+    util::{id::EntityId, secret_hash::SecretHasher},
+};
 
 #[derive(Clone)]
 pub struct ServerState {
     pub pool: sqlx::Pool<Sqlite>,
     pub bind_addr: SocketAddr,
     pub links: Arc<ServerLinks>,
+    pub resource_servers: Arc<HashMap<String, String>>,
+    pub registration_policy: Arc<RegistrationPolicy>,
+    pub secret_hasher: Arc<SecretHasher>,
 }
 
 #[async_trait]
@@ -52,6 +61,38 @@ pub async fn init() -> anyhow::Result<ServerState> {
             .trim_end_matches('/'),
     )?)?);
 
+    let resource_servers = Arc::new(
+        dotenvy::var("RESOURCE_SERVERS")
+            .ok()
+            .iter()
+            .flat_map(|x| x.split(','))
+            .filter(|x| !x.is_empty())
+            .map(|x| {
+                x.split_once(':')
+                    .map(|(id, secret)| (id.to_string(), secret.to_string()))
+                    .with_context(|| "parsing RESOURCE_SERVERS variable")
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?,
+    );
+
+    let registration_policy = Arc::new(RegistrationPolicy::from_env()?);
+
+    let secret_hasher = Arc::new(SecretHasher::from_env()?);
+
+    Client::hash_legacy_secrets(&pool, &secret_hasher)
+        .await
+        .with_context(|| "hashing client secrets")?;
+
+    AuthSession::hash_legacy_uids(&pool)
+        .await
+        .with_context(|| "hashing session ids")?;
+    AccessToken::hash_legacy_uids(&pool)
+        .await
+        .with_context(|| "hashing access tokens")?;
+    RefreshToken::hash_legacy_uids(&pool)
+        .await
+        .with_context(|| "hashing refresh tokens")?;
+
     let has_keys = sqlx::query!("SELECT EXISTS(SELECT id from jwt_keys WHERE id=id) AS has_keys")
         .fetch_one(&pool)
         .await?
@@ -68,6 +109,9 @@ pub async fn init() -> anyhow::Result<ServerState> {
         pool,
         bind_addr,
         links,
+        resource_servers,
+        registration_policy,
+        secret_hasher,
     })
 }
 
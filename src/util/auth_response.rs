@@ -0,0 +1,156 @@
+use std::{fmt::Debug, sync::Arc};
+
+use askama::Template;
+use axum::response::{IntoResponse, Redirect, Response};
+use openidconnect::{core::CoreAuthErrorResponseType, SigningError};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use time::{Duration, OffsetDateTime};
+use url::Url;
+
+use crate::{
+    error::ApiError, model::signing_keys::SigningKey, state::ServerState, util::id::EntityId,
+};
+
+use super::jwt;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResponseMode {
+    #[default]
+    #[serde(rename = "query")]
+    Query,
+    #[serde(rename = "fragment")]
+    Fragment,
+    #[serde(rename = "form_post")]
+    FormPost,
+    // JARM, section 2.3
+    #[serde(rename = "jwt")]
+    Jwt,
+    #[serde(rename = "query.jwt")]
+    QueryJwt,
+    #[serde(rename = "fragment.jwt")]
+    FragmentJwt,
+    #[serde(rename = "form_post.jwt")]
+    FormPostJwt,
+}
+
+impl ResponseMode {
+    pub fn is_jwt(self) -> bool {
+        !matches!(
+            self,
+            ResponseMode::Query | ResponseMode::Fragment | ResponseMode::FormPost
+        )
+    }
+
+    pub fn is_query(self) -> bool {
+        matches!(self, ResponseMode::Query | ResponseMode::QueryJwt)
+    }
+
+    // OAuth 2.0 Multiple Response Type Encoding Practices, section 2.1: anything but
+    // response_type=code uses the fragment instead of the query.
+    pub fn resolve(mode: Option<ResponseMode>, code_only: bool) -> ResponseMode {
+        match mode {
+            None | Some(ResponseMode::Query) if code_only => ResponseMode::Query,
+            None | Some(ResponseMode::Query) => ResponseMode::Fragment,
+            Some(ResponseMode::Jwt | ResponseMode::QueryJwt) if code_only => ResponseMode::QueryJwt,
+            Some(ResponseMode::Jwt | ResponseMode::QueryJwt) => ResponseMode::FragmentJwt,
+            Some(mode) => mode,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ResponseSigner {
+    key: Arc<SigningKey>,
+    issuer: Url,
+    client_id: EntityId,
+}
+
+impl ResponseSigner {
+    pub async fn new(client_id: EntityId, state: &ServerState) -> Result<Self, ApiError> {
+        Ok(ResponseSigner {
+            key: Arc::new(SigningKey::get_current(&state.pool).await?),
+            issuer: state.links.issuer.clone(),
+            client_id,
+        })
+    }
+
+    fn sign(&self, params: Vec<(&'static str, String)>) -> Result<String, SigningError> {
+        let mut claims = Map::new();
+
+        claims.insert("iss".to_string(), Value::String(self.issuer.to_string()));
+        claims.insert("aud".to_string(), Value::String(self.client_id.to_string()));
+        claims.insert(
+            "exp".to_string(),
+            Value::from((OffsetDateTime::now_utc() + Duration::minutes(10)).unix_timestamp()),
+        );
+
+        for (k, v) in params {
+            claims.insert(k.to_string(), Value::String(v));
+        }
+
+        jwt::sign(&claims, &self.key, None)
+    }
+}
+
+impl Debug for ResponseSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseSigner")
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Template)]
+#[template(path = "form_post.html")]
+struct FormPostTemplate {
+    action: String,
+    params: Vec<(&'static str, String)>,
+}
+
+pub struct AuthResponse<'a> {
+    pub redirect_uri: &'a Url,
+    pub state: &'a str,
+    pub mode: ResponseMode,
+    pub signer: Option<&'a ResponseSigner>,
+}
+
+impl AuthResponse<'_> {
+    pub fn send(&self, mut params: Vec<(&'static str, String)>) -> Response {
+        params.push(("state", self.state.to_string()));
+
+        if let (true, Some(signer)) = (self.mode.is_jwt(), self.signer) {
+            params = match signer.sign(params) {
+                Ok(response) => vec![("response", response)],
+                Err(err) => return ApiError::from(err).into_response(),
+            };
+        }
+
+        let mut redirect_to = self.redirect_uri.clone();
+
+        match self.mode {
+            ResponseMode::Query | ResponseMode::Jwt | ResponseMode::QueryJwt => {
+                redirect_to.query_pairs_mut().extend_pairs(params);
+
+                Redirect::to(redirect_to.as_str()).into_response()
+            }
+            ResponseMode::Fragment | ResponseMode::FragmentJwt => {
+                redirect_to.set_fragment(Some(&serde_urlencoded::to_string(params).unwrap()));
+
+                Redirect::to(redirect_to.as_str()).into_response()
+            }
+            ResponseMode::FormPost | ResponseMode::FormPostJwt => FormPostTemplate {
+                action: redirect_to.to_string(),
+                params,
+            }
+            .into_response(),
+        }
+    }
+
+    pub fn error(&self, error: CoreAuthErrorResponseType, error_desc: &str) -> ApiError {
+        ApiError::FromAxum(Box::new(self.send(vec![
+            ("error", error.as_ref().to_string()),
+            ("error_description", error_desc.to_string()),
+        ])))
+    }
+}
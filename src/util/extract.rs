@@ -2,22 +2,34 @@ use axum::response::IntoResponse;
 use axum::{
     extract::FromRequestParts,
     http::{request::Parts, StatusCode},
-    response::Redirect,
 };
+use chrono::{TimeZone, Utc};
 use openidconnect::{
     core::{
-        CoreAuthDisplay, CoreAuthErrorResponseType, CoreAuthPrompt, CoreIdToken, CoreResponseMode,
-        CoreResponseType,
+        CoreAuthDisplay, CoreAuthErrorResponseType, CoreAuthPrompt, CoreGrantType, CoreIdToken,
+        CoreIdTokenVerifier, CoreResponseType,
     },
-    LanguageTag,
+    ClientId, IssuerUrl, JsonWebKeySet, LanguageTag, Nonce, ResponseTypes,
 };
-use serde::{de::Visitor, Deserialize, Deserializer};
-use time::Duration;
+use serde::{
+    de::{IntoDeserializer, Visitor},
+    Deserialize, Deserializer,
+};
+use time::{Duration, OffsetDateTime};
 use url::Url;
 
 use crate::error::ApiError;
+use crate::model::{
+    clients::Client, pushed_auth_requests::PushedAuthRequest, request_objects::RequestObject,
+    signing_keys::SigningKey,
+};
+use crate::oidc::oidc_config::{response_types_contain, response_types_supported};
+use crate::state::ServerState;
 
+use super::auth_response::{AuthResponse, ResponseMode, ResponseSigner};
 use super::id::EntityId;
+use super::pkce::{CodeChallenge, CodeChallengeMethod};
+use super::request_object;
 use super::scopes::Scopes;
 
 #[derive(Deserialize, Debug)]
@@ -26,87 +38,367 @@ pub struct OidcAuthRequestHead {
     pub redirect_uri: url::Url,
     pub state: String,
     #[serde(skip)]
+    pub pushed_request_uri: Option<String>,
+    #[serde(skip)]
+    request_object_jti: Option<(String, i64)>,
+    #[serde(skip)]
+    response_mode: ResponseMode,
+    #[serde(skip)]
+    signer: Option<ResponseSigner>,
+    #[serde(skip)]
     query_remaining: String,
 }
 
+#[derive(Deserialize)]
+struct ResponseModeQuery {
+    response_mode: Option<ResponseMode>,
+}
+
+#[derive(Deserialize)]
+struct ResponseTypeQuery {
+    response_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RequestObjectQuery {
+    client_id: Option<EntityId>,
+    request: Option<String>,
+    request_uri: Option<String>,
+}
+
 #[axum::async_trait]
-impl<S: Send + Sync> FromRequestParts<S> for OidcAuthRequestHead {
+impl FromRequestParts<ServerState> for OidcAuthRequestHead {
     type Rejection = ApiError;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let query = parts.uri.query().unwrap_or_default();
-        let mut req: OidcAuthRequestHead = serde_urlencoded::from_str(query).map_err(|x| {
-            problemdetails::new(StatusCode::BAD_REQUEST)
-                .with_type("https://basique.top/mini-oidc/error/invalid_auth_request")
-                .with_detail(x.to_string())
-        })?;
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let mut query = parts.uri.query().unwrap_or_default().to_string();
+        let mut pushed_request_uri = None;
+        let mut request_object_jti = None;
+
+        let mut outer: RequestObjectQuery =
+            serde_urlencoded::from_str(&query).map_err(invalid_auth_request)?;
+
+        // RFC 9126, section 4: only the pushed parameters are used.
+        if let Some(request_uri) = outer
+            .request_uri
+            .clone()
+            .filter(|x| x.starts_with(PushedAuthRequest::REQUEST_URI_PREFIX))
+        {
+            let Some(par) = PushedAuthRequest::get(&request_uri, &state.pool)
+                .await?
+                .filter(|x| Some(x.client_id) == outer.client_id)
+                .filter(|x| x.expires > OffsetDateTime::now_utc())
+            else {
+                return Err(invalid_auth_request(
+                    "request_uri is invalid or has expired",
+                ));
+            };
+
+            query = par.params;
+            outer = serde_urlencoded::from_str(&query).map_err(invalid_auth_request)?;
+            pushed_request_uri = Some(request_uri);
+        }
+
+        // RFC 9101, section 6.3: request object claims replace query parameters.
+        if outer.request.is_some() || outer.request_uri.is_some() {
+            let client = match outer.client_id {
+                Some(client_id) => Client::get(client_id, &state.pool).await?,
+                None => None,
+            };
+
+            let Some(client) = client else {
+                return Err(invalid_auth_request("client_id is invalid"));
+            };
+
+            let request = match (outer.request, outer.request_uri) {
+                (Some(request), None) => request,
+                (None, Some(request_uri)) => request_object::fetch(&client, &request_uri)
+                    .await
+                    .map_err(invalid_auth_request)?,
+                _ => {
+                    return Err(invalid_auth_request(
+                        "request and request_uri can't be used together",
+                    ))
+                }
+            };
+
+            let verified = request_object::verify(&client, &request, &state.links.issuer)
+                .await
+                .map_err(invalid_auth_request)?;
+
+            let mut params: Vec<(String, String)> =
+                serde_urlencoded::from_str(&query).map_err(invalid_auth_request)?;
+
+            params.retain(|(k, _)| {
+                k != "request" && k != "request_uri" && !verified.params.iter().any(|(c, _)| c == k)
+            });
+            params.extend(verified.params);
+
+            request_object_jti = Some((verified.jti, verified.expires));
+
+            query = serde_urlencoded::to_string(&params).unwrap();
+        }
+
+        let mut req: OidcAuthRequestHead =
+            serde_urlencoded::from_str(&query).map_err(invalid_auth_request)?;
 
-        req.query_remaining = query.to_string();
+        // Errors are delivered with the requested response mode whenever it's valid.
+        let response_mode = serde_urlencoded::from_str::<ResponseModeQuery>(&query)
+            .ok()
+            .and_then(|x| x.response_mode);
+
+        let code_only = serde_urlencoded::from_str::<ResponseTypeQuery>(&query)
+            .ok()
+            .and_then(|x| x.response_type)
+            .is_none_or(|x| x == "code");
+
+        req.response_mode = ResponseMode::resolve(response_mode, code_only);
+
+        if req.response_mode.is_jwt() {
+            req.signer = Some(ResponseSigner::new(req.client_id, state).await?);
+        }
+
+        req.pushed_request_uri = pushed_request_uri;
+        req.request_object_jti = request_object_jti;
+        req.query_remaining = query;
 
         Ok(req)
     }
 }
 
+fn invalid_auth_request(detail: impl ToString) -> ApiError {
+    problemdetails::new(StatusCode::BAD_REQUEST)
+        .with_type("https://basique.top/mini-oidc/error/invalid_auth_request")
+        .with_detail(detail.to_string())
+        .into()
+}
+
 impl OidcAuthRequestHead {
+    fn response(&self) -> AuthResponse<'_> {
+        AuthResponse {
+            redirect_uri: &self.redirect_uri,
+            state: &self.state,
+            mode: self.response_mode,
+            signer: self.signer.as_ref(),
+        }
+    }
+
+    pub fn check_par(&self, require_par: bool) -> Result<(), ApiError> {
+        if require_par && self.pushed_request_uri.is_none() {
+            return Err(self.response().error(
+                CoreAuthErrorResponseType::InvalidRequest,
+                "Client requires pushed authorization requests.",
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn next(self) -> Result<OidcAuthRequest, ApiError> {
-        let query = &self.query_remaining;
+        let mut req: OidcAuthRequest =
+            serde_urlencoded::from_str(&self.query_remaining).map_err(|x| {
+                self.response()
+                    .error(CoreAuthErrorResponseType::InvalidRequest, &x.to_string())
+            })?;
+
+        let code_only = req.is_code_only();
+
+        // Multiple Response Type Encoding Practices, section 2.1
+        if !code_only && req.response_mode.is_some_and(|x| x.is_query()) {
+            return Err(self.response().error(
+                CoreAuthErrorResponseType::InvalidRequest,
+                "Query response mode can't be used with this response type.",
+            ));
+        }
+
+        req.response_mode = Some(ResponseMode::resolve(req.response_mode, code_only));
+        req.signer = self.signer;
+        req.request_object_jti = self.request_object_jti;
 
-        Ok(serde_urlencoded::from_str(query).map_err(|x| {
-            error_redirect(
-                &self.redirect_uri,
-                &self.state,
+        // OIDC Core, section 3.1.2.1
+        if req.has_prompt(CoreAuthPrompt::None) && req.prompt.len() > 1 {
+            return Err(req.error(
                 CoreAuthErrorResponseType::InvalidRequest,
-                &x.to_string(),
-            )
-        })?)
+                "prompt=none can't be combined with other prompt values.",
+            ));
+        }
+
+        Ok(req)
     }
 }
 
 impl OidcAuthRequest {
-    pub fn proceed(&self, code: &str) -> impl IntoResponse {
-        let mut redirect_to = self.redirect_uri.clone();
+    fn response(&self) -> AuthResponse<'_> {
+        AuthResponse {
+            redirect_uri: &self.redirect_uri,
+            state: &self.state,
+            mode: self.response_mode.unwrap_or_default(),
+            signer: self.signer.as_ref(),
+        }
+    }
 
-        redirect_to
-            .query_pairs_mut()
-            .append_pair("code", code)
-            .append_pair("state", &self.state);
+    pub fn proceed(&self, params: Vec<(&'static str, String)>) -> impl IntoResponse {
+        self.response().send(params)
+    }
 
-        Redirect::to(redirect_to.as_str())
+    pub fn returns(&self, response_type: CoreResponseType) -> bool {
+        self.response_type.contains(&response_type)
+    }
+
+    pub fn has_prompt(&self, prompt: CoreAuthPrompt) -> bool {
+        self.prompt.contains(&prompt)
+    }
+
+    pub async fn id_token_hint_subject(
+        &self,
+        state: &ServerState,
+    ) -> Result<Option<EntityId>, ApiError> {
+        let Some(id_token) = &self.id_token_hint else {
+            return Ok(None);
+        };
+
+        let keys = JsonWebKeySet::new(
+            SigningKey::get_all(&state.pool)
+                .await?
+                .values()
+                .map(|x| x.into_jwk())
+                .collect(),
+        );
+
+        // Expired ID tokens are still fine as hints.
+        let verifier = CoreIdTokenVerifier::new_public_client(
+            ClientId::new(self.client_id.to_string()),
+            IssuerUrl::from_url(state.links.issuer.clone()),
+            keys,
+        )
+        .set_time_fn(|| Utc.timestamp_opt(0, 0).unwrap());
+
+        id_token
+            .claims(&verifier, |_: Option<&Nonce>| Ok::<(), String>(()))
+            .ok()
+            .and_then(|x| EntityId::try_from(x.subject().as_str()).ok())
+            .map(Some)
+            .ok_or_else(|| {
+                self.error(
+                    CoreAuthErrorResponseType::InvalidRequest,
+                    "id_token_hint is invalid.",
+                )
+            })
+    }
+
+    fn is_code_only(&self) -> bool {
+        self.response_type
+            .iter()
+            .all(|x| *x == CoreResponseType::Code)
+    }
+
+    pub fn check_client(&self, client: &Client) -> Result<(), ApiError> {
+        if !response_types_contain(&response_types_supported(), &self.response_type) {
+            return Err(self.error(
+                CoreAuthErrorResponseType::UnsupportedResponseType,
+                "Response type is not supported.",
+            ));
+        }
+
+        if !response_types_contain(&client.response_types, &self.response_type) {
+            return Err(self.error(
+                CoreAuthErrorResponseType::UnauthorizedClient,
+                "Client is not allowed to use this response type.",
+            ));
+        }
+
+        // OIDC Dynamic Client Registration, section 2: hybrid flows need both grants.
+        let code_allowed = !self.returns(CoreResponseType::Code)
+            || client.allows_grant(CoreGrantType::AuthorizationCode.as_ref());
+        let implicit_allowed =
+            self.is_code_only() || client.allows_grant(CoreGrantType::Implicit.as_ref());
+
+        if !code_allowed || !implicit_allowed {
+            return Err(self.error(
+                CoreAuthErrorResponseType::UnauthorizedClient,
+                "Client is not allowed to use this grant type.",
+            ));
+        }
+
+        if !client.allows_scope(&self.scope) {
+            return Err(self.error(
+                CoreAuthErrorResponseType::InvalidScope,
+                "Client is not allowed to request this scope.",
+            ));
+        }
+
+        // OIDC Core, sections 3.2.2.1 and 3.3.2.11
+        if self.returns(CoreResponseType::IdToken) && self.nonce.is_none() {
+            return Err(self.error(
+                CoreAuthErrorResponseType::InvalidRequest,
+                "nonce is required when an ID token is returned.",
+            ));
+        }
+
+        Ok(())
     }
 
     pub fn error(&self, error: CoreAuthErrorResponseType, error_desc: &str) -> ApiError {
-        error_redirect(&self.redirect_uri, &self.state, error, error_desc)
+        self.response().error(error, error_desc)
     }
-}
 
-fn error_redirect(
-    redirect_uri: &Url,
-    state: &str,
-    error: CoreAuthErrorResponseType,
-    error_desc: &str,
-) -> ApiError {
-    let mut redirect_to = redirect_uri.clone();
-
-    redirect_to
-        .query_pairs_mut()
-        .append_pair("error", error.as_ref())
-        .append_pair("error_description", error_desc)
-        .append_pair("state", &state);
-
-    ApiError::FromAxum(Redirect::to(redirect_to.as_str()).into_response())
+    // Like pushed requests, a request object is used up once a response has been sent for it.
+    pub async fn consume_request_object(&self, state: &ServerState) -> Result<(), ApiError> {
+        let Some((jti, expires)) = &self.request_object_jti else {
+            return Ok(());
+        };
+
+        if !RequestObject::record_jti(self.client_id, jti, *expires, &state.pool).await? {
+            tracing::warn!(
+                "Request object replay detected for client {}",
+                self.client_id
+            );
+
+            return Err(self.error(
+                // openidconnect misspells InvalidRequestObject.
+                CoreAuthErrorResponseType::Extension("invalid_request_object".to_string()),
+                "Request object has already been used.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn code_challenge(&self, require_pkce: bool) -> Result<Option<CodeChallenge>, ApiError> {
+        match (&self.code_challenge, self.code_challenge_method) {
+            (Some(challenge), method) => Ok(Some(CodeChallenge {
+                challenge: challenge.clone(),
+                method: method.unwrap_or(CodeChallengeMethod::Plain),
+            })),
+            (None, None) if !require_pkce => Ok(None),
+            (None, None) => Err(self.error(
+                CoreAuthErrorResponseType::InvalidRequest,
+                "Client requires PKCE, but code_challenge is missing.",
+            )),
+            (None, Some(_)) => Err(self.error(
+                CoreAuthErrorResponseType::InvalidRequest,
+                "code_challenge_method given without code_challenge.",
+            )),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct OidcAuthRequest {
     pub scope: Scopes,
-    pub response_type: CoreResponseType,
+    pub response_type: ResponseTypes<CoreResponseType>,
     pub client_id: EntityId,
     pub redirect_uri: Url,
     pub state: String,
-    pub response_mode: Option<CoreResponseMode>,
+    pub response_mode: Option<ResponseMode>,
     pub nonce: Option<String>,
     pub display: Option<CoreAuthDisplay>,
-    pub prompt: Option<CoreAuthPrompt>,
+    #[serde(deserialize_with = "deserialize_prompt")]
+    #[serde(default)]
+    pub prompt: Vec<CoreAuthPrompt>,
     #[serde(deserialize_with = "deserialize_max_age")]
     #[serde(default)]
     pub max_age: Option<Duration>,
@@ -115,6 +407,12 @@ pub struct OidcAuthRequest {
     pub ui_locales: Vec<LanguageTag>,
     pub id_token_hint: Option<CoreIdToken>,
     pub login_hint: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<CodeChallengeMethod>,
+    #[serde(skip)]
+    signer: Option<ResponseSigner>,
+    #[serde(skip)]
+    request_object_jti: Option<(String, i64)>,
     // acr_values
 }
 
@@ -148,6 +446,31 @@ fn deserialize_max_age<'de, D: Deserializer<'de>>(
     deserialize.deserialize_i64(MaxAgeVisitor)
 }
 
+fn deserialize_prompt<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<CoreAuthPrompt>, D::Error> {
+    struct PromptVisitor;
+
+    impl<'de> Visitor<'de> for PromptVisitor {
+        type Value = Vec<CoreAuthPrompt>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("space separated prompt values")
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            v.split(' ')
+                .map(|x| CoreAuthPrompt::deserialize(x.into_deserializer()))
+                .collect()
+        }
+    }
+
+    deserializer.deserialize_str(PromptVisitor)
+}
+
 fn deserialize_ui_locales<'de, D: Deserializer<'de>>(
     deserializer: D,
 ) -> Result<Vec<LanguageTag>, D::Error> {
@@ -0,0 +1,108 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use openidconnect::{
+    core::{CoreJsonWebKey, CoreJwsSigningAlgorithm},
+    JsonWebKey, JsonWebKeyId, PrivateSigningKey, SigningError,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::model::signing_keys::SigningKey;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JwtHeader {
+    pub alg: CoreJwsSigningAlgorithm,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<JsonWebKeyId>,
+}
+
+#[derive(Error, Debug)]
+pub enum JwtError {
+    #[error("malformed JWT")]
+    Malformed,
+    #[error("invalid JWT JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("no key matches the JWT")]
+    NoMatchingKey,
+    #[error("JWT signature is invalid")]
+    InvalidSignature,
+}
+
+pub fn sign<T: Serialize>(
+    claims: &T,
+    key: &SigningKey,
+    typ: Option<&str>,
+) -> Result<String, SigningError> {
+    let alg = CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256;
+    let header = JwtHeader {
+        alg: alg.clone(),
+        typ: typ.map(|x| x.to_string()),
+        kid: Some(JsonWebKeyId::new(key.id.to_string())),
+    };
+
+    let mut token = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).unwrap()),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap())
+    );
+
+    let signature = key.key.sign(&alg, token.as_bytes())?;
+
+    token.push('.');
+    token.push_str(&URL_SAFE_NO_PAD.encode(signature));
+
+    Ok(token)
+}
+
+pub fn decode_unverified<T: DeserializeOwned>(token: &str) -> Result<(JwtHeader, T), JwtError> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(claims)) = (parts.next(), parts.next()) else {
+        return Err(JwtError::Malformed);
+    };
+
+    let header = URL_SAFE_NO_PAD
+        .decode(header)
+        .map_err(|_| JwtError::Malformed)?;
+    let claims = URL_SAFE_NO_PAD
+        .decode(claims)
+        .map_err(|_| JwtError::Malformed)?;
+
+    Ok((
+        serde_json::from_slice(&header)?,
+        serde_json::from_slice(&claims)?,
+    ))
+}
+
+pub fn verify<T: DeserializeOwned>(
+    token: &str,
+    keys: &[CoreJsonWebKey],
+) -> Result<(JwtHeader, T), JwtError> {
+    let Some((message, signature)) = token.rsplit_once('.') else {
+        return Err(JwtError::Malformed);
+    };
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| JwtError::Malformed)?;
+
+    let (header, claims) = decode_unverified::<T>(token)?;
+
+    let mut candidates = keys
+        .iter()
+        .filter(|x| header.kid.is_none() || x.key_id() == header.kid.as_ref())
+        .peekable();
+
+    if candidates.peek().is_none() {
+        return Err(JwtError::NoMatchingKey);
+    }
+
+    if candidates.any(|x| {
+        x.verify_signature(&header.alg, message.as_bytes(), &signature)
+            .is_ok()
+    }) {
+        Ok((header, claims))
+    } else {
+        Err(JwtError::InvalidSignature)
+    }
+}
@@ -1,8 +1,16 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
 
+pub mod auth_response;
 pub mod csrf;
 pub mod id;
+pub mod jwt;
+pub mod pkce;
+pub mod redirect_uri;
+pub mod request_object;
 pub mod scopes;
+pub mod secret_hash;
 pub mod template;
 pub mod extract;
 
@@ -13,3 +21,8 @@ pub fn gen_secret() -> String {
         .map(char::from)
         .collect::<String>()
 }
+
+// Bearer secrets are only stored as digests, so a database leak doesn't hand out sessions or tokens.
+pub fn hash_secret(secret: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(secret.as_bytes()))
+}
@@ -0,0 +1,42 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeChallengeMethod {
+    S256,
+    #[serde(rename = "plain")]
+    Plain,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CodeChallenge {
+    pub challenge: String,
+    pub method: CodeChallengeMethod,
+}
+
+impl CodeChallenge {
+    pub fn verify(&self, verifier: &str) -> bool {
+        // RFC 7636, section 4.1
+        let verifier_valid = (43..=128).contains(&verifier.len())
+            && verifier
+                .chars()
+                .all(|x| x.is_ascii_alphanumeric() || "-._~".contains(x));
+
+        if !verifier_valid {
+            return false;
+        }
+
+        match self.method {
+            CodeChallengeMethod::S256 => URL_SAFE_NO_PAD
+                .encode(Sha256::digest(verifier.as_bytes()))
+                .as_bytes()
+                .ct_eq(self.challenge.as_bytes())
+                .into(),
+            CodeChallengeMethod::Plain => {
+                verifier.as_bytes().ct_eq(self.challenge.as_bytes()).into()
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use url::{Host, Url};
+
+pub fn is_loopback(uri: &Url) -> bool {
+    match uri.host() {
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        Some(Host::Domain(domain)) => domain == "localhost",
+        None => false,
+    }
+}
+
+// Hosts the server shouldn't be made to send requests to on a client's behalf.
+pub fn is_internal(uri: &Url) -> bool {
+    if is_loopback(uri) {
+        return true;
+    }
+
+    match uri.host() {
+        Some(Host::Ipv4(ip)) => ip.is_private() || ip.is_link_local() || ip.is_unspecified(),
+        Some(Host::Ipv6(ip)) => {
+            // fc00::/7 is unique local and fe80::/10 is link-local.
+            let first = ip.segments()[0];
+            ip.is_unspecified() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+        }
+        Some(Host::Domain(domain)) => domain.ends_with(".localhost"),
+        None => true,
+    }
+}
+
+// RFC 8252, section 7.1: private-use schemes are reverse domain names, like com.example.app.
+pub fn is_private_use_scheme(uri: &Url) -> bool {
+    uri.scheme().contains('.')
+}
+
+// RFC 8252, section 7.3: native apps listen on an ephemeral port, so it isn't compared.
+pub fn matches(registered: &Url, requested: &Url, native: bool) -> bool {
+    if registered == requested {
+        return true;
+    }
+
+    if !native || registered.scheme() != "http" || !is_loopback(registered) {
+        return false;
+    }
+
+    let mut registered = registered.clone();
+    let mut requested = requested.clone();
+
+    if registered.set_port(None).is_err() || requested.set_port(None).is_err() {
+        return false;
+    }
+
+    registered == requested
+}
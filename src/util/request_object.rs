@@ -0,0 +1,164 @@
+use openidconnect::{
+    http::{header::ACCEPT, HeaderMap, HeaderValue, Method, StatusCode},
+    reqwest::async_http_client,
+    HttpRequest,
+};
+use serde_json::{Map, Value};
+use thiserror::Error;
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::model::{clients::Client, request_objects::RequestObject};
+use crate::oidc::oidc_config::request_object_algs_supported;
+
+use super::jwt::{self, JwtError};
+
+#[derive(Error, Debug)]
+pub enum RequestObjectError {
+    #[error("request_uri is not registered for this client")]
+    UnregisteredRequestUri,
+    #[error("failed to fetch request object: {0}")]
+    Fetch(String),
+    #[error("client has no keys to verify request objects with")]
+    NoKeys,
+    #[error("request object is signed with an unsupported algorithm")]
+    UnsupportedAlg,
+    #[error("request object is invalid: {0}")]
+    Jwt(#[from] JwtError),
+    #[error("request object claim {0} is invalid")]
+    InvalidClaim(&'static str),
+}
+
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// RFC 9101, section 5.2: request objects can only be fetched from registered URIs.
+pub async fn fetch(client: &Client, request_uri: &str) -> Result<String, RequestObjectError> {
+    let Some(url) = client
+        .request_uris
+        .iter()
+        .find(|x| x.as_str() == request_uri)
+        .map(|x| x.url().clone())
+    else {
+        return Err(RequestObjectError::UnregisteredRequestUri);
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        ACCEPT,
+        HeaderValue::from_static("application/oauth-authz-req+jwt"),
+    );
+
+    let res = tokio::time::timeout(
+        FETCH_TIMEOUT,
+        async_http_client(HttpRequest {
+            url,
+            method: Method::GET,
+            headers,
+            body: vec![],
+        }),
+    )
+    .await
+    .map_err(|_| RequestObjectError::Fetch("timed out".to_string()))?
+    .map_err(|x| RequestObjectError::Fetch(x.to_string()))?;
+
+    if res.status_code != StatusCode::OK {
+        return Err(RequestObjectError::Fetch(format!(
+            "server returned {}",
+            res.status_code
+        )));
+    }
+
+    String::from_utf8(res.body)
+        .map(|x| x.trim().to_string())
+        .map_err(|x| RequestObjectError::Fetch(x.to_string()))
+}
+
+pub struct VerifiedRequest {
+    pub params: Vec<(String, String)>,
+    pub jti: String,
+    pub expires: i64,
+}
+
+// RFC 9101, section 6.3 and 10.8: request objects are bound to the client and this server, and
+// short-lived, so that a captured one can't be replayed.
+pub async fn verify(
+    client: &Client,
+    request: &str,
+    issuer: &Url,
+) -> Result<VerifiedRequest, RequestObjectError> {
+    let Some(keys) = client.keys().await.filter(|x| !x.is_empty()) else {
+        return Err(RequestObjectError::NoKeys);
+    };
+
+    let (header, claims) = jwt::verify::<Map<String, Value>>(request, &keys)?;
+
+    if !request_object_algs_supported().contains(&header.alg) {
+        return Err(RequestObjectError::UnsupportedAlg);
+    }
+
+    let client_id = client.id.to_string();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    if claims.get("client_id").and_then(|x| x.as_str()) != Some(&client_id) {
+        return Err(RequestObjectError::InvalidClaim("client_id"));
+    }
+
+    if claims.get("iss").and_then(|x| x.as_str()) != Some(&client_id) {
+        return Err(RequestObjectError::InvalidClaim("iss"));
+    }
+
+    let audience_matches = |x: &Value| {
+        x.as_str()
+            .and_then(|x| Url::parse(x).ok())
+            .is_some_and(|x| x == *issuer)
+    };
+
+    let audience_valid = match claims.get("aud") {
+        Some(Value::Array(aud)) => aud.iter().any(audience_matches),
+        Some(aud) => audience_matches(aud),
+        None => false,
+    };
+
+    if !audience_valid {
+        return Err(RequestObjectError::InvalidClaim("aud"));
+    }
+
+    let Some(expires) = claims.get("exp").and_then(|x| x.as_i64()) else {
+        return Err(RequestObjectError::InvalidClaim("exp"));
+    };
+
+    if expires <= now || expires > now + RequestObject::MAX_LIFETIME.whole_seconds() {
+        return Err(RequestObjectError::InvalidClaim("exp"));
+    }
+
+    if claims
+        .get("nbf")
+        .is_some_and(|x| x.as_i64().map(|x| x > now).unwrap_or(true))
+    {
+        return Err(RequestObjectError::InvalidClaim("nbf"));
+    }
+
+    let Some(jti) = claims
+        .get("jti")
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string())
+    else {
+        return Err(RequestObjectError::InvalidClaim("jti"));
+    };
+
+    let params = claims
+        .into_iter()
+        .filter(|(k, _)| !matches!(k.as_str(), "iss" | "aud" | "exp" | "nbf" | "iat" | "jti"))
+        .filter_map(|(k, v)| match v {
+            Value::Null => None,
+            Value::String(v) => Some((k, v)),
+            v => Some((k, v.to_string())),
+        })
+        .collect();
+
+    Ok(VerifiedRequest {
+        params,
+        jti,
+        expires,
+    })
+}
@@ -0,0 +1,97 @@
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use sha2::Sha256;
+
+// Client secrets are random, so a keyed hash is enough to keep them useless outside the server.
+pub struct SecretHasher {
+    key: Vec<u8>,
+}
+
+impl SecretHasher {
+    // HMAC-SHA256 keys shorter than the hash output weaken it.
+    pub const MIN_KEY_LEN: usize = 32;
+
+    // Changing the key invalidates every client secret and registration token.
+    pub fn from_env() -> anyhow::Result<SecretHasher> {
+        let key = dotenvy::var("SECRET_HASH_KEY")
+            .with_context(|| "reading SECRET_HASH_KEY environment variable")?;
+
+        if key.len() < SecretHasher::MIN_KEY_LEN {
+            anyhow::bail!(
+                "SECRET_HASH_KEY must be at least {} bytes long",
+                SecretHasher::MIN_KEY_LEN
+            );
+        }
+
+        Ok(SecretHasher {
+            key: key.into_bytes(),
+        })
+    }
+
+    fn mac(&self, secret: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(secret.as_bytes());
+        mac
+    }
+
+    pub fn hash(&self, secret: &str) -> String {
+        URL_SAFE_NO_PAD.encode(self.mac(secret).finalize().into_bytes())
+    }
+
+    pub fn verify(&self, secret: &str, hash: &str) -> bool {
+        let Ok(hash) = URL_SAFE_NO_PAD.decode(hash) else {
+            return false;
+        };
+
+        self.mac(secret).verify_slice(&hash).is_ok()
+    }
+
+    // Hashes are no good for client_secret_jwt, which needs the secret itself as an HMAC key.
+    fn cipher(&self) -> LessSafeKey {
+        let key = self.mac("client secret encryption").finalize().into_bytes();
+
+        LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).unwrap())
+    }
+
+    // The client ID is authenticated too, so encrypted secrets can't be moved between clients.
+    pub fn encrypt(&self, secret: &str, client_id: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut sealed = secret.as_bytes().to_vec();
+        self.cipher()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(client_id.as_bytes()),
+                &mut sealed,
+            )
+            .unwrap();
+
+        URL_SAFE_NO_PAD.encode([&nonce[..], &sealed].concat())
+    }
+
+    pub fn decrypt(&self, encrypted: &str, client_id: &str) -> Option<String> {
+        let encrypted = URL_SAFE_NO_PAD.decode(encrypted).ok()?;
+
+        if encrypted.len() < NONCE_LEN {
+            return None;
+        }
+
+        let (nonce, sealed) = encrypted.split_at(NONCE_LEN);
+        let mut sealed = sealed.to_vec();
+
+        let secret = self
+            .cipher()
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(nonce).ok()?,
+                Aad::from(client_id.as_bytes()),
+                &mut sealed,
+            )
+            .ok()?;
+
+        String::from_utf8(secret.to_vec()).ok()
+    }
+}